@@ -0,0 +1,59 @@
+//! Throughput of the `BufRead` lexer compared with the slice lexer, over a synthetic calendar.
+
+use criterion::{criterion_group, criterion_main, Criterion, Throughput};
+use std::fmt::Write;
+
+fn calendar(events: usize) -> String {
+    let mut cal = String::from("BEGIN:VCALENDAR\r\nVERSION:2.0\r\nPRODID:-//ickle//bench//EN\r\n");
+    for i in 0..events {
+        write!(
+            cal,
+            "BEGIN:VEVENT\r\n\
+             UID:{i}@example.com\r\n\
+             DTSTAMP:20200101T000000Z\r\n\
+             DTSTART;TZID=America/New_York:20200102T090000\r\n\
+             DTEND;TZID=America/New_York:20200102T100000\r\n\
+             RRULE:FREQ=WEEKLY;BYDAY=MO,WE,FR;COUNT=10\r\n\
+             SUMMARY;LANGUAGE=en:Meeting number {i}\\, with an escaped comma\r\n\
+             DESCRIPTION:A description long enough that it has to be folded onto a sec\r\n \
+             ond line\\, as real calendars do.\r\n\
+             LOCATION:Room {i}\r\n\
+             ATTENDEE;CN=\"Someone, Jr.\";ROLE=REQ-PARTICIPANT:mailto:someone@example.com\r\n\
+             END:VEVENT\r\n",
+            i = i
+        )
+        .unwrap();
+    }
+    cal.push_str("END:VCALENDAR\r\n");
+    cal
+}
+
+fn lex(c: &mut Criterion) {
+    let input = calendar(10_000);
+    let mut group = c.benchmark_group("lex");
+    group.throughput(Throughput::Bytes(input.len() as u64));
+    group.bench_function("bufread", |b| {
+        b.iter(|| {
+            let mut lexer = ickle::Lexer::new(input.as_bytes());
+            let mut n = 0;
+            while let Some(line) = lexer.lex_content_line().unwrap() {
+                n += line.value().len();
+            }
+            n
+        })
+    });
+    group.bench_function("slice", |b| {
+        b.iter(|| {
+            let mut lexer = ickle::slice::Lexer::new(input.as_bytes());
+            let mut n = 0;
+            while let Some(line) = lexer.lex_content_line().unwrap() {
+                n += line.value().len();
+            }
+            n
+        })
+    });
+    group.finish();
+}
+
+criterion_group!(benches, lex);
+criterion_main!(benches);
@@ -0,0 +1,314 @@
+//! Lexing from a tokio `AsyncBufRead`, e.g. an HTTP response body, without buffering all of it first.
+//!
+//! Each content line is read asynchronously, with its folded continuation lines, and then lexed by
+//! `slice::Lexer`; unfolding, escaping, options and warnings are the same as for `Lexer`.
+
+use crate::component::{self, Component, Nesting, Splitter};
+use crate::{slice, Bad, ContentLine, ParserOptions, Position, Result, Span, Warning};
+use futures_util::stream::{self, Stream};
+use std::collections::VecDeque;
+use tokio::io::{AsyncBufRead, AsyncBufReadExt};
+
+pub struct AsyncLexer<R> {
+    reader: R,
+    options: ParserOptions,
+    /// The physical lines of the content line being read.
+    buf: Vec<u8>,
+    /// Lexed lines not yet returned; a buffer holds several if they are separated by bare CRs.
+    pending: VecDeque<Result<ContentLine>>,
+    line: usize,
+    offset: usize,
+    warnings: Vec<Warning>,
+    /// An unrecoverable error has been returned.
+    failed: bool,
+}
+
+impl<R: AsyncBufRead + Unpin> AsyncLexer<R> {
+    /// A lexer with `ParserOptions::default()`, as `Lexer::new`.
+    pub fn new(reader: R) -> Self {
+        Self::with_options(reader, ParserOptions::default())
+    }
+
+    pub fn with_options(reader: R, options: ParserOptions) -> Self {
+        AsyncLexer {
+            reader,
+            options,
+            buf: Vec::new(),
+            pending: VecDeque::new(),
+            line: 1,
+            offset: 0,
+            warnings: Vec::new(),
+            failed: false,
+        }
+    }
+
+    pub fn options(&self) -> &ParserOptions {
+        &self.options
+    }
+
+    /// Position of the next unread line in the input.
+    pub fn position(&self) -> Position {
+        Position { line: self.line, column: 1, offset: self.offset }
+    }
+
+    /// Warnings about the input read so far. They accumulate until taken with `take_warnings`.
+    pub fn warnings(&self) -> &[Warning] {
+        &self.warnings
+    }
+
+    pub fn take_warnings(&mut self) -> Vec<Warning> {
+        std::mem::take(&mut self.warnings)
+    }
+
+    pub fn finish(self) -> R {
+        self.reader
+    }
+
+    /// Read the next content line. Blank lines are skipped. After a recoverable error (see
+    /// `Error::is_recoverable`), lexing can continue with the next line; after an I/O error, the lexer
+    /// returns `None`.
+    pub async fn lex_content_line(&mut self) -> Result<Option<ContentLine>> {
+        while !self.failed {
+            if let Some(next) = self.pending.pop_front() {
+                return next.map(Some);
+            }
+            if !self.read_unfolded().await? {
+                return Ok(None);
+            }
+            let mut lexer = slice::Lexer::starting_at(&self.buf, self.options, self.line, self.offset);
+            while let Some(next) = lexer.lex_content_line().transpose() {
+                self.pending.push_back(next.map(slice::ContentLine::into_owned));
+            }
+            let end = lexer.position();
+            self.warnings.append(&mut lexer.take_warnings());
+            self.line = end.line;
+            self.offset = end.offset;
+        }
+        Ok(None)
+    }
+
+    /// Read the physical lines of the next content line into the buffer. Returns false at end of input.
+    /// When retaining raw text, blank lines before and after it are read too; `slice::Lexer` decides which
+    /// content line each belongs to.
+    async fn read_unfolded(&mut self) -> Result<bool> {
+        self.buf.clear();
+        loop {
+            match self.reader.read_until(b'\n', &mut self.buf).await {
+                Ok(0) => break,
+                Ok(_) => (),
+                Err(e) => return Err(self.io_error(e)),
+            }
+            let blank = self.options.retain_raw && self.buf.iter().all(|&c| c == b'\r' || c == b'\n');
+            match self.reader.fill_buf().await {
+                Ok([b' ', ..]) | Ok([b'\t', ..]) => (),
+                Ok([b'\r', ..]) | Ok([b'\n', ..]) if self.options.retain_raw => (),
+                Ok(_) if blank => (),
+                Ok(_) => break,
+                Err(e) => return Err(self.io_error(e)),
+            }
+        }
+        Ok(!self.buf.is_empty())
+    }
+
+    fn io_error(&mut self, e: std::io::Error) -> crate::Error {
+        self.failed = true;
+        let pos = Position { line: self.line, column: 1, offset: self.offset + self.buf.len() };
+        Bad::Io(e).at(Span::at(pos))
+    }
+
+    /// The next content line, skipping malformed ones with a warning if the options allow it.
+    async fn next_line(&mut self) -> component::Result<Option<ContentLine>> {
+        loop {
+            match self.lex_content_line().await {
+                Ok(coli) => return Ok(coli),
+                Err(e) if e.is_recoverable() && self.options.skip_malformed_lines => {
+                    self.warnings.push(component::skipped_line(&e))
+                }
+                Err(e) => return Err(e.into()),
+            }
+        }
+    }
+
+    /// Read the next top-level component, as `Component::parse`.
+    pub async fn parse_component(&mut self) -> component::Result<Option<Component>> {
+        let mut nesting = Nesting::default();
+        while let Some(coli) = self.next_line().await? {
+            if let Some(done) = nesting.push(coli, 0)? {
+                return Ok(Some(done));
+            }
+        }
+        nesting.finish(self.position())?;
+        Ok(None)
+    }
+
+    /// The remaining content lines, as a `Stream`.
+    pub fn into_stream(self) -> impl Stream<Item=Result<ContentLine>> {
+        stream::unfold(self, |mut lexer| async move {
+            let next = lexer.lex_content_line().await.transpose()?;
+            Some((next, lexer))
+        })
+    }
+}
+
+/// Reads the subcomponents of top-level components one at a time, as `component::ComponentStream`.
+pub struct AsyncComponentStream<R> {
+    lexer: AsyncLexer<R>,
+    splitter: Splitter,
+    failed: bool,
+}
+
+impl<R: AsyncBufRead + Unpin> AsyncComponentStream<R> {
+    pub fn new(lexer: AsyncLexer<R>) -> Self {
+        AsyncComponentStream { lexer, splitter: Splitter::default(), failed: false }
+    }
+
+    /// The current top-level component, without its subcomponents; see `ComponentStream::calendar`.
+    pub fn calendar(&self) -> Option<&Component> {
+        self.splitter.calendar()
+    }
+
+    pub fn lexer(&self) -> &AsyncLexer<R> {
+        &self.lexer
+    }
+
+    pub fn into_lexer(self) -> AsyncLexer<R> {
+        self.lexer
+    }
+
+    /// The next subcomponent. Returns `None` at end of input, and after an error.
+    pub async fn next(&mut self) -> Option<component::Result<Component>> {
+        if self.failed {
+            return None;
+        }
+        let next = self.read().await.transpose();
+        self.failed = !matches!(next, Some(Ok(_)));
+        next
+    }
+
+    async fn read(&mut self) -> component::Result<Option<Component>> {
+        while let Some(coli) = self.lexer.next_line().await? {
+            if let Some(done) = self.splitter.push(coli)? {
+                return Ok(Some(done));
+            }
+        }
+        self.splitter.finish(self.lexer.position())?;
+        Ok(None)
+    }
+
+    /// The remaining subcomponents, as a `Stream`.
+    pub fn into_stream(self) -> impl Stream<Item=component::Result<Component>> {
+        stream::unfold(self, |mut components| async move {
+            let next = components.next().await?;
+            Some((next, components))
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::component::ComponentStream;
+    use crate::Lexer;
+    use futures_util::StreamExt;
+    use tokio::io::BufReader;
+
+    type Lexed = std::result::Result<(String, String, Span, Option<Vec<u8>>), (crate::ErrorKind, Span)>;
+
+    fn summarize(result: Result<ContentLine>) -> Lexed {
+        result.map(|coli| (coli.name().as_str().to_owned(), coli.value().to_owned(), coli.span(), coli.raw().map(<[u8]>::to_vec)))
+            .map_err(|e| (e.kind(), e.span()))
+    }
+
+    async fn lex(input: &[u8], capacity: usize, options: ParserOptions) -> (Vec<Lexed>, Vec<Warning>) {
+        let mut lexer = AsyncLexer::with_options(BufReader::with_capacity(capacity, input), options);
+        let mut lines = Vec::new();
+        while let Some(next) = lexer.lex_content_line().await.transpose() {
+            lines.push(summarize(next));
+        }
+        (lines, lexer.take_warnings())
+    }
+
+    const INPUT: &[u8] = b"BEGIN:VCALENDAR\r\nBEGIN:VEVENT\r\nUID:1\r\nSUMMARY;LANGUAGE=en:Lunch\\, then\r\n  a walk\\n\r\n\
+                           DESCRIPTION:\r\n x\r\n\ty\r\n z\r\n\r\nATTENDEE;CN=\"Doe, ^'J^'\":mailto:j@\r\n x\r\n\
+                           SUMMARY;CN:x\r\nEND:VEVENT\nEND:VCALENDAR\r\n\r\n";
+
+    #[tokio::test]
+    async fn agrees_with_buffered_lexer() {
+        let options = [ParserOptions::strict(), ParserOptions::default(), ParserOptions::lenient().retain_raw(true)];
+        let inputs: [&[u8]; 3] = [INPUT, b"\r\nUID:1\r\n\rUID:2\r\n x\n\n\r\n\r\r\nUID:3", b"\n\r\n"];
+        for (input, &options) in inputs.iter().flat_map(|input| options.iter().map(move |options| (input, options))) {
+            let mut lexer = Lexer::with_options(*input, options);
+            let expected: Vec<_> = lexer.content_lines().map(summarize).collect();
+            // Small buffers split lines, folds and line breaks between reads.
+            for capacity in [1, 2, 3, 5, 8, 13, 1024] {
+                let (lines, warnings) = lex(input, capacity, options).await;
+                assert_eq!(lines, expected, "capacity {}", capacity);
+                assert_eq!(warnings, lexer.warnings(), "capacity {}", capacity);
+            }
+        }
+    }
+
+    #[tokio::test]
+    async fn folds_across_reads() {
+        let (lines, _) = lex(b"DESCRIPTION:a\r\n b\r\n\tc\r\n", 1, ParserOptions::strict()).await;
+        assert_eq!(lines.len(), 1);
+        let (name, value, span, _) = lines[0].as_ref().unwrap();
+        assert_eq!((name.as_str(), value.as_str()), ("DESCRIPTION", "abc"));
+        assert_eq!((span.end().line(), span.end().offset()), (3, 21));
+    }
+
+    #[tokio::test]
+    async fn lossless() {
+        // Without the malformed line.
+        let input = std::str::from_utf8(INPUT).unwrap().replace("SUMMARY;CN:x\r\n", "").into_bytes();
+        let reader = BufReader::with_capacity(4, std::io::Cursor::new(&input));
+        let mut lexer = AsyncLexer::with_options(reader, ParserOptions::lenient().retain_raw(true));
+        let mut written = Vec::new();
+        while let Some(coli) = lexer.lex_content_line().await.transpose() {
+            written.extend_from_slice(coli.unwrap().raw().unwrap());
+        }
+        assert_eq!(written, input);
+    }
+
+    #[tokio::test]
+    async fn streams() {
+        let lines: Vec<_> = AsyncLexer::new(BufReader::with_capacity(3, INPUT)).into_stream().collect().await;
+        assert_eq!(lines.len(), 9);
+        assert_eq!(lines.iter().filter(|r| r.is_err()).count(), 1);
+
+        let mut components = AsyncComponentStream::new(AsyncLexer::new(BufReader::with_capacity(3, INPUT)));
+        let event = components.next().await.unwrap().unwrap();
+        let expected = ComponentStream::new(Lexer::new(INPUT)).next().unwrap().unwrap();
+        assert_eq!(event.properties().map(|coli| coli.value()).collect::<Vec<_>>(),
+                   expected.properties().map(|coli| coli.value()).collect::<Vec<_>>());
+        assert!(components.calendar().unwrap().is(component::IanaComponent::Vcalendar));
+        assert!(components.next().await.is_none());
+        let warnings = components.lexer().warnings();
+        assert!(warnings.iter().any(|w| w.code() == crate::WarningCode::SkippedLine));
+
+        let calendar = AsyncLexer::new(INPUT).parse_component().await.unwrap().unwrap();
+        assert_eq!(calendar.events().count(), 1);
+        let e = AsyncLexer::with_options(INPUT, ParserOptions::strict()).parse_component().await.unwrap_err();
+        assert_eq!((e.kind(), e.line()), (crate::ErrorKind::Syntax, 13));
+        let components: Vec<_> = AsyncComponentStream::new(AsyncLexer::new(&b"BEGIN:VCALENDAR\r\n"[..])).into_stream().collect().await;
+        assert_eq!(components.len(), 1);
+        assert_eq!(components[0].as_ref().unwrap_err().kind(), crate::ErrorKind::Nesting);
+    }
+
+    struct Failing;
+    impl tokio::io::AsyncRead for Failing {
+        fn poll_read(self: std::pin::Pin<&mut Self>, _: &mut std::task::Context<'_>, _: &mut tokio::io::ReadBuf<'_>)
+                     -> std::task::Poll<std::io::Result<()>> {
+            std::task::Poll::Ready(Err(std::io::Error::other("connection reset")))
+        }
+    }
+
+    #[tokio::test]
+    async fn io_errors() {
+        let mut lexer = AsyncLexer::new(BufReader::new(Failing));
+        let e = lexer.lex_content_line().await.unwrap_err();
+        assert_eq!(e.kind(), crate::ErrorKind::Io);
+        assert!(!e.is_recoverable());
+        assert!(lexer.lex_content_line().await.unwrap().is_none());
+    }
+}
@@ -0,0 +1,493 @@
+use crate::define_identifier_set;
+use crate::diagnostic::{detail, Diagnostic};
+use crate::{ContentLine, ErrorKind, Lexer, Position, Property, IanaProperty, Span, Warning, WarningCode};
+use std::io::BufRead;
+use std::str::FromStr;
+
+define_identifier_set!(IanaComponent,
+    Vcalendar, b"VCALENDAR",
+    Vevent,    b"VEVENT",
+    Vtodo,     b"VTODO",
+    Vjournal,  b"VJOURNAL",
+    Vfreebusy, b"VFREEBUSY",
+    Vtimezone, b"VTIMEZONE",
+    Valarm,    b"VALARM",
+    Standard,  b"STANDARD",
+    Daylight,  b"DAYLIGHT",
+);
+
+#[derive(Debug, PartialEq, Eq, Clone)]
+pub enum ComponentName {
+    Iana(IanaComponent),
+    /// An x-name or an iana-token not in the initial registry.
+    Other(String),
+}
+impl ComponentName {
+    /// Component names are case-insensitive; registered names are matched in any case.
+    fn from_value(s: &str) -> Self {
+        match IanaComponent::from_str(&s.to_ascii_uppercase()) {
+            Ok(iana) => ComponentName::Iana(iana),
+            Err(()) => ComponentName::Other(s.to_owned()),
+        }
+    }
+
+    pub fn as_str(&self) -> &str {
+        match self {
+            ComponentName::Iana(iana) => iana.as_str(),
+            ComponentName::Other(s) => s,
+        }
+    }
+}
+
+#[derive(Debug)]
+enum Bad {
+    Lex(Box<crate::Error>),
+    /// END with no open component.
+    UnmatchedEnd { name: String },
+    /// END naming a component other than the innermost open one.
+    MismatchedEnd { begin: ComponentName, begin_pos: Position, end: String },
+    /// Input ended inside a component.
+    Unterminated { name: ComponentName, begin_pos: Position },
+    /// Property outside of any component.
+    Orphan { name: Property },
+}
+
+#[derive(Debug)]
+pub struct Error {
+    bad: Bad,
+    span: Span,
+}
+impl Error {
+    pub fn kind(&self) -> ErrorKind {
+        match &self.bad {
+            Bad::Lex(e) => e.kind(),
+            Bad::UnmatchedEnd { .. } | Bad::MismatchedEnd { .. } | Bad::Unterminated { .. } | Bad::Orphan { .. } =>
+                ErrorKind::Nesting,
+        }
+    }
+
+    pub fn line(&self) -> usize {
+        self.span.start().line()
+    }
+
+    pub fn position(&self) -> Position {
+        self.span.start()
+    }
+
+    pub fn span(&self) -> Span {
+        self.span
+    }
+
+    /// Render the error against the input it came from.
+    pub fn diagnostic<'s>(&self, source: &'s [u8]) -> Diagnostic<'s> {
+        Diagnostic::new(source, self.span, self.kind(), detail(|f| self.fmt_detail(f)))
+    }
+
+    pub(crate) fn fmt_detail(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        match &self.bad {
+            Bad::Lex(e) => e.fmt_detail(f),
+            Bad::UnmatchedEnd { name } => write!(f, "END:{} without matching BEGIN", name),
+            Bad::MismatchedEnd { begin, begin_pos, end } =>
+                write!(f, "END:{} does not match BEGIN:{} on line {}", end, begin.as_str(), begin_pos.line()),
+            Bad::Unterminated { name, begin_pos } =>
+                write!(f, "input ended before END:{} (opened on line {})", name.as_str(), begin_pos.line()),
+            Bad::Orphan { name } => write!(f, "property {:?} outside of any component", name),
+        }
+    }
+}
+impl std::fmt::Display for Error {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        write!(f, "{}: ", self.span.start())?;
+        self.fmt_detail(f)
+    }
+}
+impl std::error::Error for Error {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match &self.bad {
+            Bad::Lex(e) => Some(&**e),
+            _ => None,
+        }
+    }
+}
+impl From<crate::Error> for Error {
+    fn from(e: crate::Error) -> Self {
+        let span = e.span();
+        Error { bad: Bad::Lex(Box::new(e)), span }
+    }
+}
+pub type Result<T> = std::result::Result<T, Error>;
+
+/// A component and its nested subcomponents, e.g. a VCALENDAR containing VEVENTs containing VALARMs.
+#[derive(Clone, Debug)]
+pub struct Component {
+    name: ComponentName,
+    pub(crate) properties: Vec<ContentLine>,
+    children: Vec<Component>,
+    span: Span,
+    /// The BEGIN and END lines as read, for lossless rewriting.
+    pub(crate) begin: Option<ContentLine>,
+    pub(crate) end: Option<ContentLine>,
+    /// For each subcomponent read, the number of properties that preceded it.
+    pub(crate) child_positions: Vec<usize>,
+}
+impl Component {
+    fn new(name: ComponentName, span: Span) -> Self {
+        Component { name, properties: Vec::new(), children: Vec::new(), span, begin: None, end: None, child_positions: Vec::new() }
+    }
+
+    pub fn name(&self) -> &ComponentName {
+        &self.name
+    }
+
+    /// Returns true if this is the IANA component type `name`.
+    pub fn is(&self, name: IanaComponent) -> bool {
+        self.name == ComponentName::Iana(name)
+    }
+
+    /// The component's own properties, in order; BEGIN/END lines and subcomponents are not included.
+    pub fn properties(&self) -> impl Iterator<Item=&ContentLine> {
+        self.properties.iter()
+    }
+
+    /// The first instance of the given property, if any.
+    pub fn property(&self, name: IanaProperty) -> Option<&ContentLine> {
+        self.properties.iter().find(|coli| coli.name() == &Property::Iana(name))
+    }
+
+    pub fn properties_mut(&mut self) -> &mut Vec<ContentLine> {
+        &mut self.properties
+    }
+
+    pub fn children(&self) -> impl Iterator<Item=&Component> {
+        self.children.iter()
+    }
+
+    pub fn children_mut(&mut self) -> &mut Vec<Component> {
+        &mut self.children
+    }
+
+    /// Direct subcomponents of the given type.
+    pub fn children_named(&self, name: IanaComponent) -> impl Iterator<Item=&Component> {
+        self.children.iter().filter(move |c| c.is(name))
+    }
+
+    pub fn events(&self) -> impl Iterator<Item=&Component> {
+        self.children_named(IanaComponent::Vevent)
+    }
+
+    pub fn todos(&self) -> impl Iterator<Item=&Component> {
+        self.children_named(IanaComponent::Vtodo)
+    }
+
+    pub fn timezones(&self) -> impl Iterator<Item=&Component> {
+        self.children_named(IanaComponent::Vtimezone)
+    }
+
+    pub fn alarms(&self) -> impl Iterator<Item=&Component> {
+        self.children_named(IanaComponent::Valarm)
+    }
+
+    /// Line number of the component's BEGIN.
+    pub fn line(&self) -> usize {
+        self.span.start().line()
+    }
+
+    /// Position of the component's BEGIN.
+    pub fn position(&self) -> Position {
+        self.span.start()
+    }
+
+    /// Span of the component's BEGIN line.
+    pub fn span(&self) -> Span {
+        self.span
+    }
+
+    /// Read the next top-level component (normally a VCALENDAR) from the lexer, including all of its
+    /// subcomponents. Returns `None` at end of input. Malformed content lines are skipped with a warning
+    /// (see `Lexer::warnings`) if the lexer's options allow it (see `ParserOptions::skip_malformed_lines`).
+    pub fn parse<S: BufRead>(lexer: &mut Lexer<S>) -> Result<Option<Component>> {
+        let mut nesting = Nesting::default();
+        while let Some(coli) = next_line(lexer)? {
+            if let Some(done) = nesting.push(coli, 0)? {
+                return Ok(Some(done));
+            }
+        }
+        nesting.finish(lexer.position())?;
+        Ok(None)
+    }
+
+    /// Read all top-level components remaining in the input.
+    pub fn parse_all<S: BufRead>(lexer: &mut Lexer<S>) -> Result<Vec<Component>> {
+        let mut components = Vec::new();
+        while let Some(c) = Component::parse(lexer)? {
+            components.push(c);
+        }
+        Ok(components)
+    }
+}
+
+/// The next content line, skipping malformed ones with a warning if the options allow it.
+fn next_line<S: BufRead>(lexer: &mut Lexer<S>) -> Result<Option<ContentLine>> {
+    loop {
+        match lexer.content_lines().next() {
+            None => return Ok(None),
+            Some(Ok(coli)) => return Ok(Some(coli)),
+            Some(Err(e)) if e.is_recoverable() && lexer.options.skip_malformed_lines => {
+                lexer.warnings.push(skipped_line(&e))
+            }
+            Some(Err(e)) => return Err(e.into()),
+        }
+    }
+}
+
+pub(crate) fn skipped_line(e: &crate::Error) -> Warning {
+    Warning::new(WarningCode::SkippedLine, e.span(), format!("skipped malformed content line: {}", detail(|f| e.fmt_detail(f))))
+}
+
+/// Assembles content lines into components.
+#[derive(Default)]
+pub(crate) struct Nesting {
+    open: Vec<Component>,
+}
+impl Nesting {
+    /// Add the next line. Returns the component it ends, if that leaves no more than `depth` components open.
+    pub(crate) fn push(&mut self, coli: ContentLine, depth: usize) -> Result<Option<Component>> {
+        let span = coli.span();
+        match coli.name() {
+            Property::Begin => {
+                let mut begun = Component::new(ComponentName::from_value(coli.value()), span);
+                begun.begin = Some(coli);
+                self.open.push(begun);
+            }
+            Property::End => {
+                let mut done = self.open.pop().ok_or_else(|| Error {
+                    bad: Bad::UnmatchedEnd { name: coli.value().to_owned() },
+                    span,
+                })?;
+                if !done.name.as_str().eq_ignore_ascii_case(coli.value()) {
+                    return Err(Error {
+                        bad: Bad::MismatchedEnd { begin: done.name, begin_pos: done.span.start(), end: coli.value().to_owned() },
+                        span,
+                    });
+                }
+                done.end = Some(coli);
+                if self.open.len() <= depth {
+                    return Ok(Some(done));
+                }
+                if let Some(parent) = self.open.last_mut() {
+                    parent.child_positions.push(parent.properties.len());
+                    parent.children.push(done);
+                }
+            }
+            name => match self.open.last_mut() {
+                Some(current) => current.properties.push(coli),
+                None => return Err(Error { bad: Bad::Orphan { name: name.clone() }, span }),
+            },
+        }
+        Ok(None)
+    }
+
+    /// Check that the input did not end inside a component.
+    pub(crate) fn finish(&mut self, pos: Position) -> Result<()> {
+        match self.open.pop() {
+            Some(c) => Err(Error { bad: Bad::Unterminated { name: c.name, begin_pos: c.span.start() }, span: Span::at(pos) }),
+            None => Ok(()),
+        }
+    }
+}
+
+/// Assembles content lines into the subcomponents of top-level components.
+#[derive(Default)]
+pub(crate) struct Splitter {
+    nesting: Nesting,
+    /// The last top-level component to have ended.
+    calendar: Option<Component>,
+}
+impl Splitter {
+    /// Add the next line. Returns the subcomponent it ends, if any.
+    pub(crate) fn push(&mut self, coli: ContentLine) -> Result<Option<Component>> {
+        match self.nesting.push(coli, 1)? {
+            Some(calendar) if self.nesting.open.is_empty() => {
+                self.calendar = Some(calendar);
+                Ok(None)
+            }
+            done => Ok(done),
+        }
+    }
+
+    pub(crate) fn calendar(&self) -> Option<&Component> {
+        self.nesting.open.first().or(self.calendar.as_ref())
+    }
+
+    pub(crate) fn finish(&mut self, pos: Position) -> Result<()> {
+        self.nesting.finish(pos)
+    }
+}
+
+/// Reads the subcomponents of top-level components one at a time, so that a large calendar can be
+/// processed without holding all of it in memory.
+///
+/// Each VEVENT, VTODO, VTIMEZONE etc. is yielded complete, with its own subcomponents, as soon as its END
+/// is read. The properties of the enclosing VCALENDAR are available from `calendar`. Iteration ends after
+/// an error.
+pub struct ComponentStream<S> {
+    lexer: Lexer<S>,
+    splitter: Splitter,
+    failed: bool,
+}
+impl<S: BufRead> ComponentStream<S> {
+    pub fn new(lexer: Lexer<S>) -> Self {
+        ComponentStream { lexer, splitter: Splitter::default(), failed: false }
+    }
+
+    /// The current top-level component, without its subcomponents. Its properties are those read so far;
+    /// properties that follow a subcomponent are only present once it has been yielded.
+    pub fn calendar(&self) -> Option<&Component> {
+        self.splitter.calendar()
+    }
+
+    pub fn lexer(&self) -> &Lexer<S> {
+        &self.lexer
+    }
+
+    pub fn into_lexer(self) -> Lexer<S> {
+        self.lexer
+    }
+
+    fn read(&mut self) -> Result<Option<Component>> {
+        while let Some(coli) = next_line(&mut self.lexer)? {
+            if let Some(done) = self.splitter.push(coli)? {
+                return Ok(Some(done));
+            }
+        }
+        self.splitter.finish(self.lexer.position())?;
+        Ok(None)
+    }
+}
+impl<S: BufRead> Iterator for ComponentStream<S> {
+    type Item = Result<Component>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        if self.failed {
+            return None;
+        }
+        let next = self.read().transpose();
+        self.failed = !matches!(next, Some(Ok(_)));
+        next
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn parse(input: &str) -> Result<Option<Component>> {
+        Component::parse(&mut Lexer::new(input.as_bytes()))
+    }
+
+    #[test]
+    fn nested_components() {
+        let input = "BEGIN:VCALENDAR\r\nVERSION:2.0\r\nBEGIN:VEVENT\r\nUID:1\r\nBEGIN:VALARM\r\nACTION:DISPLAY\r\n\
+                     END:VALARM\r\nEND:VEVENT\r\nBEGIN:VTODO\r\nUID:2\r\nEND:VTODO\r\nEND:VCALENDAR\r\n";
+        let cal = parse(input).unwrap().unwrap();
+        assert!(cal.is(IanaComponent::Vcalendar));
+        assert_eq!(cal.properties().count(), 1);
+        assert_eq!(cal.children().count(), 2);
+        let event = cal.events().next().unwrap();
+        assert_eq!(event.property(IanaProperty::Uid).unwrap().value(), "1");
+        assert_eq!(event.alarms().count(), 1);
+        assert_eq!(event.line(), 3);
+        assert_eq!(cal.todos().count(), 1);
+    }
+
+    #[test]
+    fn end_matches_begin_case_insensitively() {
+        let cal = parse("BEGIN:VCALENDAR\r\nBEGIN:vevent\r\nUID:1\r\nEND:VEVENT\r\nEND:vcalendar\r\n").unwrap().unwrap();
+        assert!(cal.is(IanaComponent::Vcalendar));
+        assert_eq!(cal.events().count(), 1);
+    }
+
+    #[test]
+    fn mismatched_end() {
+        let e = parse("BEGIN:VCALENDAR\r\nBEGIN:VEVENT\r\nEND:VTODO\r\nEND:VCALENDAR\r\n").unwrap_err();
+        assert_eq!(e.kind(), ErrorKind::Nesting);
+        assert_eq!(e.line(), 3);
+    }
+
+    #[test]
+    fn unmatched_end_and_orphan() {
+        assert_eq!(parse("END:VEVENT\r\n").unwrap_err().kind(), ErrorKind::Nesting);
+        assert_eq!(parse("UID:1\r\n").unwrap_err().kind(), ErrorKind::Nesting);
+    }
+
+    #[test]
+    fn unterminated() {
+        let e = parse("BEGIN:VCALENDAR\r\nBEGIN:VEVENT\r\n").unwrap_err();
+        assert_eq!(e.kind(), ErrorKind::Nesting);
+        assert_eq!(e.line(), 3);
+    }
+
+    #[test]
+    fn parse_all_reads_every_calendar() {
+        let input = "BEGIN:VCALENDAR\r\nEND:VCALENDAR\r\nBEGIN:VCALENDAR\r\nEND:VCALENDAR\r\n";
+        assert_eq!(Component::parse_all(&mut Lexer::new(input.as_bytes())).unwrap().len(), 2);
+        assert!(parse("").unwrap().is_none());
+    }
+
+    #[test]
+    fn malformed_lines() {
+        let input = "BEGIN:VCALENDAR\r\nBEGIN:VEVENT\r\nUID:1\r\nNOT-A-PROPERTY:x\r\nSUMMARY;CN:x\r\nEND:VEVENT\r\nEND:VCALENDAR\r\n";
+        let mut lexer = Lexer::new(input.as_bytes());
+        let cal = Component::parse(&mut lexer).unwrap().unwrap();
+        assert_eq!(cal.events().next().unwrap().properties().count(), 1);
+        let skipped: Vec<_> = lexer.warnings().iter().map(|w| (w.code(), w.line())).collect();
+        assert_eq!(skipped, [(WarningCode::SkippedLine, 4), (WarningCode::SkippedLine, 5)]);
+
+        // Strict parsing returns the first error.
+        let mut lexer = Lexer::with_options(input.as_bytes(), crate::ParserOptions::strict());
+        let e = Component::parse(&mut lexer).unwrap_err();
+        assert_eq!((e.kind(), e.line()), (ErrorKind::UnknownProperty, 4));
+        assert!(lexer.warnings().is_empty());
+        let options = crate::ParserOptions::strict().unregistered_names(true);
+        let e = Component::parse(&mut Lexer::with_options(input.as_bytes(), options)).unwrap_err();
+        assert_eq!((e.kind(), e.line()), (ErrorKind::Syntax, 5));
+    }
+
+    #[test]
+    fn component_stream() {
+        let input = "BEGIN:VCALENDAR\r\nVERSION:2.0\r\nBEGIN:VEVENT\r\nUID:1\r\nBEGIN:VALARM\r\nACTION:DISPLAY\r\n\
+                     END:VALARM\r\nEND:VEVENT\r\nX-WR-CALNAME:Work\r\nBEGIN:VTODO\r\nUID:2\r\nEND:VTODO\r\nEND:VCALENDAR\r\n\
+                     BEGIN:VCALENDAR\r\nVERSION:2.0\r\nBEGIN:VEVENT\r\nUID:3\r\nEND:VEVENT\r\nEND:VCALENDAR\r\n";
+        let options = crate::ParserOptions::strict().unregistered_names(true);
+        let mut stream = ComponentStream::new(Lexer::with_options(input.as_bytes(), options));
+        assert!(stream.calendar().is_none());
+        let event = stream.next().unwrap().unwrap();
+        assert!(event.is(IanaComponent::Vevent));
+        assert_eq!(event.alarms().count(), 1);
+        // The calendar holds its properties, but not the subcomponents already yielded.
+        let calendar = stream.calendar().unwrap();
+        assert_eq!((calendar.properties().count(), calendar.children().count()), (1, 0));
+        let todo = stream.next().unwrap().unwrap();
+        assert_eq!(todo.property(IanaProperty::Uid).unwrap().value(), "2");
+        assert_eq!(stream.calendar().unwrap().properties().count(), 2);
+        let event = stream.next().unwrap().unwrap();
+        assert_eq!((event.property(IanaProperty::Uid).unwrap().value(), event.line()), ("3", 16));
+        assert_eq!(stream.calendar().unwrap().line(), 14);
+        assert!(stream.next().is_none());
+    }
+
+    #[test]
+    fn component_stream_ends_after_error() {
+        let input = "BEGIN:VCALENDAR\r\nBEGIN:VEVENT\r\nUID:1\r\nEND:VEVENT\r\nBEGIN:VEVENT\r\nEND:VTODO\r\n\
+                     BEGIN:VEVENT\r\nUID:2\r\nEND:VEVENT\r\nEND:VCALENDAR\r\n";
+        let mut stream = ComponentStream::new(Lexer::new(input.as_bytes()));
+        assert!(stream.next().unwrap().is_ok());
+        let e = stream.next().unwrap().unwrap_err();
+        assert_eq!((e.kind(), e.line()), (ErrorKind::Nesting, 6));
+        assert!(stream.next().is_none());
+
+        let mut stream = ComponentStream::new(Lexer::new("BEGIN:VCALENDAR\r\nBEGIN:VEVENT\r\n".as_bytes()));
+        assert_eq!(stream.next().unwrap().unwrap_err().kind(), ErrorKind::Nesting);
+        assert!(stream.next().is_none());
+    }
+}
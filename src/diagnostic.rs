@@ -0,0 +1,148 @@
+//! Rendering of errors against the input they came from, in the style of compiler diagnostics:
+//!
+//! ```text
+//! error: invalid parameter
+//!  --> line 3, column 4
+//!   |
+//! 3 |  b;FOO=1:x
+//!   |    ^^^ unknown parameter FOO
+//! ```
+//!
+//! Every physical line that the span covers is shown, with the span underlined on each.
+
+use crate::{ErrorKind, Span};
+use std::fmt;
+
+pub struct Diagnostic<'s> {
+    source: &'s [u8],
+    span: Span,
+    kind: ErrorKind,
+    message: String,
+}
+
+impl<'s> Diagnostic<'s> {
+    pub fn new(source: &'s [u8], span: Span, kind: ErrorKind, message: impl fmt::Display) -> Self {
+        Diagnostic { source, span, kind, message: message.to_string() }
+    }
+
+    pub fn span(&self) -> Span {
+        self.span
+    }
+
+    pub fn kind(&self) -> ErrorKind {
+        self.kind
+    }
+
+    pub fn message(&self) -> &str {
+        &self.message
+    }
+}
+
+/// Adapts a formatting function to `Display`.
+pub(crate) struct Detail<F>(F);
+
+pub(crate) fn detail<F: Fn(&mut fmt::Formatter) -> fmt::Result>(f: F) -> Detail<F> {
+    Detail(f)
+}
+impl<F: Fn(&mut fmt::Formatter) -> fmt::Result> fmt::Display for Detail<F> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        (self.0)(f)
+    }
+}
+
+/// The physical lines of the input, without line breaks. Breaks are CRLF, CR or LF, as in the lexer.
+fn physical_lines(source: &[u8]) -> impl Iterator<Item=&[u8]> {
+    let mut rest = source;
+    std::iter::from_fn(move || {
+        if rest.is_empty() {
+            return None;
+        }
+        let end = rest.iter().position(|&c| c == b'\r' || c == b'\n').unwrap_or(rest.len());
+        let line = &rest[..end];
+        let skip = match rest[end..] {
+            [b'\r', b'\n', ..] => 2,
+            [] => 0,
+            _ => 1,
+        };
+        rest = &rest[end + skip..];
+        Some(line)
+    })
+}
+
+/// Display width of the first `octets` octets of a line.
+fn width(line: &[u8], octets: usize) -> usize {
+    String::from_utf8_lossy(&line[..octets.min(line.len())]).chars().count() + octets.saturating_sub(line.len())
+}
+
+impl fmt::Display for Diagnostic<'_> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let (start, end) = (self.span.start(), self.span.end());
+        let last = end.line().max(start.line());
+        let gutter = last.to_string().len();
+        writeln!(f, "error: {}", self.kind)?;
+        writeln!(f, "{:gutter$}--> {}", "", start, gutter = gutter)?;
+        writeln!(f, "{:gutter$} |", "", gutter = gutter)?;
+        let lines = physical_lines(self.source).enumerate().skip(start.line().saturating_sub(1));
+        for (i, line) in lines.take(last + 1 - start.line().max(1)) {
+            let number = i + 1;
+            writeln!(f, "{:>gutter$} | {}", number, String::from_utf8_lossy(line), gutter = gutter)?;
+            // Continuation lines begin with the whitespace of the fold, which is not part of any span.
+            let from = if number == start.line() { start.column().saturating_sub(1) } else { 1 };
+            let to = if number == end.line() { end.column().saturating_sub(1) } else { line.len() };
+            let indent = width(line, from);
+            let carets = width(line, to).saturating_sub(indent).max(1);
+            write!(f, "{:gutter$} | {:indent$}{}", "", "", "^".repeat(carets), gutter = gutter, indent = indent)?;
+            if number == last {
+                write!(f, " {}", self.message)?;
+            }
+            writeln!(f)?;
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::component::Component;
+    use crate::vevent::VEvent;
+    use crate::Lexer;
+
+    fn render(input: &[u8]) -> String {
+        let mut lexer = Lexer::new(input);
+        loop {
+            if let Err(e) = lexer.lex_content_line() {
+                return e.diagnostic(input).to_string();
+            }
+        }
+    }
+
+    #[test]
+    fn folded() {
+        assert_eq!(render(b"BEGIN:VEVENT\r\nSUMMARY;X-A=a\r\n b;FOO=1:x\r\n"), "\
+error: invalid parameter
+ --> line 3, column 4
+  |
+3 |  b;FOO=1:x
+  |    ^^^ unknown parameter FOO
+");
+    }
+
+    #[test]
+    fn multiple_lines() {
+        let input = b"BEGIN:VEVENT\r\nUID:1\r\nDTSTAMP:20240101T000000Z\r\nSEQUENCE:1\r\n 2x\r\nEND:VEVENT\r\n";
+        let component = Component::parse(&mut Lexer::new(&input[..])).unwrap().unwrap();
+        let e = VEvent::from_component(&component).unwrap_err();
+        let rendered = e.diagnostic(input).to_string();
+        let lines: Vec<_> = rendered.lines().collect();
+        assert_eq!(lines[0], "error: invalid INTEGER value");
+        assert_eq!(lines[3..], ["4 | SEQUENCE:1", "  |          ^", "5 |  2x", "  |  ^^ invalid INTEGER value \"12x\""]);
+    }
+
+    #[test]
+    fn wide_characters() {
+        // Columns are counted in octets, but carets are placed by character.
+        let rendered = render("SUMMARY;X-A=\"ÉÉ\";FOO=1:x\r\n".as_bytes());
+        assert!(rendered.contains("--> line 1, column 20"));
+        assert!(rendered.ends_with(&format!("  | {}^^^ unknown parameter FOO\n", " ".repeat(17))));
+    }
+}
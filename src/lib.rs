@@ -1,4 +1,14 @@
+#[cfg(feature = "async")]
+pub mod async_lexer;
+pub mod component;
+pub mod diagnostic;
+pub mod param;
+pub mod recur;
+pub mod slice;
+pub mod tz;
 pub mod vevent;
+pub mod writer;
+mod windows_zones;
 use std::io::BufRead;
 use std::fmt::{self, Debug};
 use std::str::FromStr;
@@ -12,7 +22,7 @@ macro_rules! define_identifier_set {
             $( $Tok, )*
         }
         impl $Name {
-            fn from_bytes<'s>(s: &'s [u8]) -> std::result::Result<Self, &'s [u8]> {
+            fn from_bytes(s: &[u8]) -> std::result::Result<Self, &[u8]> {
                 use $Name::*;
                 Ok(match s {
                     $( $tok_name => $Tok, )*
@@ -22,7 +32,10 @@ macro_rules! define_identifier_set {
             pub fn as_str(self) -> &'static str {
                 use $Name::*;
                 match self {
-                    $( $Tok => unsafe { std::str::from_utf8_unchecked($tok_name) }, )*
+                    $( $Tok => {
+                        let s: &'static [u8] = $tok_name;
+                        unsafe { std::str::from_utf8_unchecked(s) }
+                    }, )*
                 }
             }
         }
@@ -109,18 +122,57 @@ define_identifier_set!(IanaParam,
     Value,         b"VALUE",
 );
 
-#[derive(Debug, PartialEq, Eq, Clone)]
+define_identifier_set!(ValueType,
+    Binary,     b"BINARY",
+    Boolean,    b"BOOLEAN",
+    CalAddress, b"CAL-ADDRESS",
+    Date,       b"DATE",
+    DateTime,   b"DATE-TIME",
+    Duration,   b"DURATION",
+    Float,      b"FLOAT",
+    Integer,    b"INTEGER",
+    Period,     b"PERIOD",
+    Recur,      b"RECUR",
+    Text,       b"TEXT",
+    Time,       b"TIME",
+    Uri,        b"URI",
+    UtcOffset,  b"UTC-OFFSET",
+);
+
+impl IanaProperty {
+    /// The value type of the property when no VALUE parameter is given.
+    pub fn default_value_type(self) -> ValueType {
+        use IanaProperty::*;
+        match self {
+            Attach | Tzurl | Url => ValueType::Uri,
+            Geo => ValueType::Float,
+            PercentComplete | Priority | Repeat | Sequence => ValueType::Integer,
+            Completed | Dtend | Due | Dtstart | RecurrenceId | Exdate | Rdate | Created | Dtstamp | LastModified =>
+                ValueType::DateTime,
+            Duration | Trigger => ValueType::Duration,
+            Freebusy => ValueType::Period,
+            Tzoffsetfrom | Tzoffsetto => ValueType::UtcOffset,
+            Attendee | Organizer => ValueType::CalAddress,
+            Exrule | Rrule => ValueType::Recur,
+            Calscale | Method | Prodid | Version | Categories | Class | Comment | Description | Location |
+            Resources | Status | Summary | Transp | Tzid | Tzname | Contact | RelatedTo | Uid | Action |
+            RequestStatus => ValueType::Text,
+        }
+    }
+}
+
+#[derive(Debug, PartialEq, Eq, Clone, Default)]
 pub enum Property {
     Iana(IanaProperty),
     Extended(String),
+    /// An iana-token not in the initial registry; only accepted with `ParserOptions::unregistered_names` (or `retain_raw`).
+    Other(String),
     Begin,
+    #[default]
     End,
 }
-impl Default for Property {
-    fn default() -> Self { Property::End }
-}
 impl Property {
-    fn from_bytes<'s>(s: &'s [u8]) -> std::result::Result<Self, &'s [u8]> {
+    fn from_bytes(s: &[u8]) -> std::result::Result<Self, &[u8]> {
         use Property::*;
         Ok(match s {
             b"BEGIN" => Begin,
@@ -128,72 +180,332 @@ impl Property {
             _ => if let Ok(iana) = IanaProperty::from_bytes(s) {
                 Iana(iana)
             } else if s.starts_with(b"X-") {
-                Extended(String::from_utf8(s.to_owned()).unwrap())
+                Extended(String::from_utf8_lossy(s).into_owned())
             } else {
                 return Err(s)
             }
         })
     }
+
+    pub fn as_str(&self) -> &str {
+        match self {
+            Property::Iana(iana) => iana.as_str(),
+            Property::Extended(name) | Property::Other(name) => name,
+            Property::Begin => "BEGIN",
+            Property::End => "END",
+        }
+    }
 }
 
 #[derive(Debug, PartialEq, Eq, Clone)]
 pub enum ParamName {
     Iana(IanaParam),
     Extended(String),
+    /// An iana-token not in the initial registry; only accepted with `ParserOptions::unregistered_names` (or `retain_raw`).
+    Other(String),
 }
 impl ParamName {
-    fn from_bytes<'s>(s: &'s [u8]) -> std::result::Result<Self, &'s [u8]> {
+    fn from_bytes(s: &[u8]) -> std::result::Result<Self, &[u8]> {
         use ParamName::*;
         if let Ok(iana) = IanaParam::from_bytes(s) {
             Ok(Iana(iana))
         } else if s.starts_with(b"X-") {
-            Ok(Extended(String::from_utf8(s.to_owned()).unwrap()))
+            Ok(Extended(String::from_utf8_lossy(s).into_owned()))
         } else {
             Err(s)
         }
     }
+
+    pub fn as_str(&self) -> &str {
+        match self {
+            ParamName::Iana(iana) => iana.as_str(),
+            ParamName::Extended(name) | ParamName::Other(name) => name,
+        }
+    }
+}
+
+/// A location in the input.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub struct Position {
+    line: usize,
+    column: usize,
+    offset: usize,
+}
+impl Position {
+    /// Physical line number, starting from 1.
+    pub fn line(&self) -> usize {
+        self.line
+    }
+
+    /// Octet position within the physical line, starting from 1.
+    pub fn column(&self) -> usize {
+        self.column
+    }
+
+    /// Octets from the start of the input.
+    pub fn offset(&self) -> usize {
+        self.offset
+    }
+}
+impl fmt::Display for Position {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "line {}, column {}", self.line, self.column)
+    }
+}
+
+/// A range of the input, from `start` up to (not including) `end`. It may span several physical lines
+/// of a folded content line.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub struct Span {
+    start: Position,
+    end: Position,
+}
+impl Span {
+    fn new(start: Position, end: Position) -> Self {
+        Span { start, end }
+    }
+
+    fn at(pos: Position) -> Self {
+        Span { start: pos, end: pos }
+    }
+
+    pub fn start(&self) -> Position {
+        self.start
+    }
+
+    pub fn end(&self) -> Position {
+        self.end
+    }
+}
+
+/// The category of an error from any stage of parsing.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ErrorKind {
+    Io,
+    /// Text that is not valid UTF-8.
+    Encoding,
+    /// A content line that is not well formed.
+    Syntax,
+    UnknownProperty,
+    BadParameter,
+    /// A value that is not valid for its data type.
+    BadValue(vevent::DataType),
+    /// BEGIN and END lines that do not nest properly, or a property outside of any component.
+    Nesting,
+    /// A violation of a rule of RFC 5545, e.g. a missing required property.
+    Constraint,
+}
+impl fmt::Display for ErrorKind {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            ErrorKind::Io => write!(f, "I/O error"),
+            ErrorKind::Encoding => write!(f, "invalid UTF-8"),
+            ErrorKind::Syntax => write!(f, "malformed content line"),
+            ErrorKind::UnknownProperty => write!(f, "unknown property"),
+            ErrorKind::BadParameter => write!(f, "invalid parameter"),
+            ErrorKind::BadValue(datatype) => write!(f, "invalid {} value", datatype),
+            ErrorKind::Nesting => write!(f, "misnested component"),
+            ErrorKind::Constraint => write!(f, "constraint violated"),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum WarningCode {
+    /// A malformed content line was skipped.
+    SkippedLine,
+    /// A name that is neither registered nor an X-name was accepted.
+    UnregisteredName,
+    /// A lowercase name was converted to uppercase.
+    LowercaseName,
+    /// A line ended with LF or CR alone.
+    BareLineBreak,
+    /// A value that was not UTF-8 was decoded as Latin-1.
+    Latin1,
+    /// A parameter that takes one value had several; only the first is used.
+    MultipleValues,
+    /// A property that is not interpreted was kept as a content line.
+    NotImplemented,
+    /// A DATE-TIME that must be UTC was not, and was taken as UTC.
+    NotUtc,
+    /// A property that may occur only once occurred again, and was ignored.
+    Duplicate,
+    /// A value that was not valid was ignored, or kept without being interpreted.
+    InvalidValue,
+}
+impl WarningCode {
+    pub fn as_str(self) -> &'static str {
+        match self {
+            WarningCode::SkippedLine => "skipped-line",
+            WarningCode::UnregisteredName => "unregistered-name",
+            WarningCode::LowercaseName => "lowercase-name",
+            WarningCode::BareLineBreak => "bare-line-break",
+            WarningCode::Latin1 => "latin1",
+            WarningCode::MultipleValues => "multiple-values",
+            WarningCode::NotImplemented => "not-implemented",
+            WarningCode::NotUtc => "not-utc",
+            WarningCode::Duplicate => "duplicate",
+            WarningCode::InvalidValue => "invalid-value",
+        }
+    }
+}
+
+/// A problem with the input that did not prevent parsing.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Warning {
+    code: WarningCode,
+    span: Span,
+    message: String,
+}
+impl Warning {
+    pub(crate) fn new(code: WarningCode, span: Span, message: String) -> Self {
+        Warning { code, span, message }
+    }
+
+    pub fn code(&self) -> WarningCode {
+        self.code
+    }
+
+    pub fn line(&self) -> usize {
+        self.span.start.line
+    }
+
+    pub fn span(&self) -> Span {
+        self.span
+    }
+
+    pub fn message(&self) -> &str {
+        &self.message
+    }
+}
+impl fmt::Display for Warning {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}: {} [{}]", self.span.start, self.message, self.code.as_str())
+    }
 }
 
 #[derive(Debug)]
 enum Bad {
-    Eof,
     Io(std::io::Error),
-    Encoding(std::string::FromUtf8Error),
+    Encoding(std::str::Utf8Error),
     Property{ name: String, params: Vec<Param>, value: String },
     Param(Vec<u8>),
+    /// An octet that cannot occur at this point in a content line; `b'\n'` for a premature line end.
+    Unexpected { found: u8, expected: &'static str },
+    /// A line ended by CR or LF alone.
+    LineBreak,
 }
 impl Bad {
     /// Return true if it will definitely not be possible to lex any further data.
     fn is_unrecoverable(&self) -> bool {
         use Bad::*;
         match self {
-            Eof | Io(..) => true,
-            Encoding(..) | Property{..} | Param(..) => false,
+            Io(..) => true,
+            Encoding(..) | Property{..} | Param(..) | Unexpected{..} | LineBreak => false,
         }
     }
-    fn at(self, line: usize) -> Error {
-        Error { condition: self, line }
+    fn at(self, span: Span) -> Error {
+        Error { condition: self, span }
     }
 }
 
 pub struct Error {
     condition: Bad,
-    line: usize,
+    span: Span,
+}
+impl Error {
+    pub fn kind(&self) -> ErrorKind {
+        match self.condition {
+            Bad::Io(_) => ErrorKind::Io,
+            Bad::Encoding(_) => ErrorKind::Encoding,
+            Bad::Property { .. } => ErrorKind::UnknownProperty,
+            Bad::Param(_) => ErrorKind::BadParameter,
+            Bad::Unexpected { .. } | Bad::LineBreak => ErrorKind::Syntax,
+        }
+    }
+
+    pub fn line(&self) -> usize {
+        self.span.start.line
+    }
+
+    pub fn position(&self) -> Position {
+        self.span.start
+    }
+
+    pub fn span(&self) -> Span {
+        self.span
+    }
+
+    /// Render the error against the input it came from.
+    pub fn diagnostic<'s>(&self, source: &'s [u8]) -> diagnostic::Diagnostic<'s> {
+        diagnostic::Diagnostic::new(source, self.span, self.kind(), diagnostic::detail(|f| self.fmt_detail(f)))
+    }
+
+    /// Returns true if the lexer skipped the offending line, and can continue with the next one.
+    pub fn is_recoverable(&self) -> bool {
+        !self.condition.is_unrecoverable()
+    }
 }
 impl Debug for Error {
     fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
-        write!(f, "While lexing line {}: {:?}", self.line, self.condition)
+        write!(f, "While lexing {}: {:?}", self.span.start, self.condition)
+    }
+}
+impl fmt::Display for Error {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}: ", self.span.start)?;
+        self.fmt_detail(f)
+    }
+}
+impl Error {
+    pub(crate) fn fmt_detail(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match &self.condition {
+            Bad::Io(e) => write!(f, "{}", e),
+            Bad::Encoding(e) => write!(f, "{}", e),
+            Bad::Property { name, params, value } =>
+                write!(f, "unknown property {} ({} params, value {:?})", name, params.len(), value),
+            Bad::Param(name) => write!(f, "unknown parameter {}", String::from_utf8_lossy(name)),
+            Bad::Unexpected { found: b'\n', expected } => write!(f, "line ended, expected {}", expected),
+            Bad::Unexpected { found, expected } =>
+                write!(f, "unexpected {:?}, expected {}", char::from(*found), expected),
+            Bad::LineBreak => write!(f, "line break is not CRLF"),
+        }
+    }
+}
+impl std::error::Error for Error {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match &self.condition {
+            Bad::Io(e) => Some(e),
+            Bad::Encoding(e) => Some(e),
+            _ => None,
+        }
     }
 }
 type Maybe<T> = std::result::Result<T, Bad>;
 pub type Result<T> = std::result::Result<T, Error>;
 
-#[derive(Clone, Debug, PartialEq, Eq)]
+#[derive(Clone, Debug)]
 pub struct Param {
     name: ParamName,
     values: Vec<String>,
+    span: Span,
 }
+impl PartialEq for Param {
+    fn eq(&self, other: &Self) -> bool {
+        self.name == other.name && self.values == other.values
+    }
+}
+impl Eq for Param {}
 impl Param {
+    pub fn new(name: ParamName, values: Vec<String>) -> Self {
+        Param { name, values, span: Span::default() }
+    }
+
+    /// Where the parameter, from its name through its last value, was in the input.
+    pub fn span(&self) -> Span {
+        self.span
+    }
+
     pub fn name(&self) -> &ParamName {
         &self.name
     }
@@ -202,15 +514,97 @@ impl Param {
     }
 }
 
+/// The type given by a VALUE parameter if present, otherwise the property's default.
+fn value_type(name: &Property, value_param: Option<&str>) -> ValueType {
+    if let Some(vt) = value_param.and_then(|v| v.parse().ok()) {
+        return vt;
+    }
+    match name {
+        Property::Iana(iana) => iana.default_value_type(),
+        Property::Extended(_) | Property::Other(_) | Property::Begin | Property::End => ValueType::Text,
+    }
+}
+
+/// Unescape a TEXT value, if it has any escapes.
+fn unescape_text(s: &str) -> Option<String> {
+    if !s.contains('\\') {
+        return None;
+    }
+    let mut out = String::with_capacity(s.len());
+    let mut chars = s.chars();
+    while let Some(c) = chars.next() {
+        out.push(match c {
+            '\\' => match chars.next() {
+                Some('n') | Some('N') => '\n',
+                Some(c) => c,
+                // A backslash at the end of the line is kept literally.
+                None => '\\',
+            },
+            c => c,
+        });
+    }
+    Some(out)
+}
+
+/// Decode the circumflex escapes of RFC 6868 in a parameter value, if it has any: `^n` is a newline,
+/// `^'` a DQUOTE and `^^` a circumflex. A circumflex followed by anything else is left as it is.
+fn decode_carets(s: &[u8]) -> Option<Vec<u8>> {
+    if !s.contains(&b'^') {
+        return None;
+    }
+    let mut out = Vec::with_capacity(s.len());
+    let mut i = 0;
+    while i < s.len() {
+        let decoded = match &s[i..] {
+            [b'^', b'n', ..] => b'\n',
+            [b'^', b'\'', ..] => b'"',
+            [b'^', b'^', ..] => b'^',
+            [c, ..] => {
+                out.push(*c);
+                i += 1;
+                continue;
+            }
+            [] => break,
+        };
+        out.push(decoded);
+        i += 2;
+    }
+    Some(out)
+}
+
+/// A warning if a parameter that takes one value has several.
+fn check_single_value(name: &ParamName, count: usize, span: Span) -> Option<Warning> {
+    let multiple = matches!(name, ParamName::Iana(IanaParam::DelegatedFrom)
+        | ParamName::Iana(IanaParam::DelegatedTo)
+        | ParamName::Iana(IanaParam::Member));
+    if multiple || count <= 1 {
+        return None;
+    }
+    let message = format!("{} has {} values; only the first is used", name.as_str(), count);
+    Some(Warning::new(WarningCode::MultipleValues, span, message))
+}
+
 #[derive(Default, Clone, Debug)]
 pub struct ContentLine {
     name: Property,
     params: Vec<Param>,
     num_params: usize,
     value: String,
-    line: usize,
+    /// The value as it appeared in the input, if unescaping changed it.
+    raw_value: Option<String>,
+    /// The value was given with `new` or `set_value` rather than read, and is escaped when written.
+    value_set: bool,
+    span: Span,
+    name_span: Span,
+    value_span: Span,
+    raw: Option<Vec<u8>>,
 }
 impl ContentLine {
+    pub fn new(name: Property, params: Vec<Param>, value: String) -> Self {
+        let num_params = params.len();
+        ContentLine { name, params, num_params, value, value_set: true, ..ContentLine::default() }
+    }
+
     pub fn name(&self) -> &Property {
         &self.name
     }
@@ -220,7 +614,7 @@ impl ContentLine {
     }
 
     pub fn values_of(&self, pn: IanaParam) -> Option<impl Iterator<Item=&str>> {
-        for param in &self.params {
+        for param in self.params() {
             if param.name() == &ParamName::Iana(pn) {
                 return Some(param.values.iter().map(|s| s.as_str()));
             }
@@ -228,119 +622,497 @@ impl ContentLine {
         None
     }
 
+    /// The first value of the parameter. The lexer warns of `WarningCode::MultipleValues` if a
+    /// parameter that takes only one value has more.
     pub fn value_of(&self, pn: IanaParam) -> Option<&str> {
-        for param in &self.params {
+        for param in self.params() {
             if param.name() == &ParamName::Iana(pn) {
-                // XXX: what if len > 1?
                 return param.values().next()
             }
         }
         None
     }
 
+    /// The value. A TEXT value is unescaped; values of other types are as they appeared in the input.
     pub fn value(&self) -> &str {
         &self.value
     }
 
+    /// The value as it appeared in the input, with any escapes, or as set with `set_value`.
+    pub fn raw_value(&self) -> &str {
+        self.raw_value.as_deref().unwrap_or(&self.value)
+    }
+
+    /// The type of the value: given by the VALUE parameter if present, otherwise the property's default.
+    pub fn value_type(&self) -> ValueType {
+        value_type(&self.name, self.value_of(IanaParam::Value))
+    }
+
     pub fn line(&self) -> usize {
-        self.line
+        self.span.start.line
+    }
+
+    /// Where the line starts in the input.
+    pub fn position(&self) -> Position {
+        self.span.start
+    }
+
+    /// Where the line was in the input, not including the line break.
+    pub fn span(&self) -> Span {
+        self.span
+    }
+
+    pub fn name_span(&self) -> Span {
+        self.name_span
+    }
+
+    pub fn value_span(&self) -> Span {
+        self.value_span
+    }
+
+    /// The line exactly as it appeared in the input, including any folding, the line break and blank
+    /// lines around it (see `ParserOptions::retain_raw`), if the lexer was retaining raw text and the
+    /// line has not been modified since.
+    pub fn raw(&self) -> Option<&[u8]> {
+        self.raw.as_deref()
+    }
+
+    pub fn set_value(&mut self, value: String) {
+        self.value = value;
+        self.raw_value = None;
+        self.value_set = true;
+        self.raw = None;
+    }
+
+    /// Replace the values of parameter `name`, adding it if not present.
+    pub fn set_param(&mut self, name: ParamName, values: Vec<String>) {
+        self.params.truncate(self.num_params);
+        match self.params.iter_mut().find(|p| p.name == name) {
+            Some(param) => param.values = values,
+            None => self.params.push(Param::new(name, values)),
+        }
+        self.num_params = self.params.len();
+        self.raw = None;
+    }
+
+    pub fn remove_param(&mut self, name: &ParamName) {
+        self.params.truncate(self.num_params);
+        self.params.retain(|p| &p.name != name);
+        self.num_params = self.params.len();
+        self.raw = None;
+    }
+}
+
+/// How strictly the lexer follows RFC 5545. Each leniency can be enabled separately; input that needs
+/// an enabled leniency is accepted with a warning, and is otherwise an error. `Lexer::new` uses the
+/// default, which enables two leniencies; `strict` enables none.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct ParserOptions {
+    unregistered_names: bool,
+    bare_line_breaks: bool,
+    lowercase_names: bool,
+    latin1_fallback: bool,
+    lenient_values: bool,
+    skip_malformed_lines: bool,
+    retain_raw: bool,
+}
+/// Strict, except that bare line breaks are accepted and malformed lines skipped. This is not `strict()`
+/// because files with LF line endings are common, and would otherwise have every line rejected, and one
+/// bad property in a feed should not lose the rest of the calendar.
+impl Default for ParserOptions {
+    fn default() -> Self {
+        Self::strict().bare_line_breaks(true).skip_malformed_lines(true)
+    }
+}
+impl ParserOptions {
+    /// No leniencies.
+    pub fn strict() -> Self {
+        ParserOptions {
+            unregistered_names: false,
+            bare_line_breaks: false,
+            lowercase_names: false,
+            latin1_fallback: false,
+            lenient_values: false,
+            skip_malformed_lines: false,
+            retain_raw: false,
+        }
+    }
+
+    /// All leniencies.
+    pub fn lenient() -> Self {
+        ParserOptions {
+            unregistered_names: true,
+            bare_line_breaks: true,
+            lowercase_names: true,
+            latin1_fallback: true,
+            lenient_values: true,
+            skip_malformed_lines: true,
+            retain_raw: false,
+        }
+    }
+
+    /// Accept property and parameter names that are neither registered nor X-names, as
+    /// `Property::Other` and `ParamName::Other`.
+    pub fn unregistered_names(mut self, allow: bool) -> Self {
+        self.unregistered_names = allow;
+        self
+    }
+
+    /// Accept lines ended by LF or CR alone.
+    pub fn bare_line_breaks(mut self, allow: bool) -> Self {
+        self.bare_line_breaks = allow;
+        self
+    }
+
+    /// Accept property and parameter names in lowercase (they are converted to uppercase).
+    pub fn lowercase_names(mut self, allow: bool) -> Self {
+        self.lowercase_names = allow;
+        self
+    }
+
+    /// Decode values that are not valid UTF-8 as Latin-1.
+    pub fn latin1_fallback(mut self, allow: bool) -> Self {
+        self.latin1_fallback = allow;
+        self
+    }
+
+    /// When assembling typed values (see `vevent::VEvent::from_component_with_options`), take values that
+    /// break a rule of RFC 5545 but can still be used: a DTSTAMP, CREATED, LAST-MODIFIED or FREEBUSY that is
+    /// not in UTC is taken as UTC, an RSVP parameter that is not a BOOLEAN is ignored, and a RANGE other
+    /// than THISANDFUTURE is kept uninterpreted.
+    pub fn lenient_values(mut self, allow: bool) -> Self {
+        self.lenient_values = allow;
+        self
+    }
+
+    /// When reading components (see `component::Component::parse`), skip a content line with a
+    /// recoverable error (see `Error::is_recoverable`) with a warning, rather than returning the error.
+    pub fn skip_malformed_lines(mut self, allow: bool) -> Self {
+        self.skip_malformed_lines = allow;
+        self
+    }
+
+    /// Keep the original text of each content line (see `ContentLine::raw`), so that lines which are
+    /// not modified can be written back exactly as they were read. Blank lines are kept with the content
+    /// line before them (or, at the start of the input, after them). Unregistered names are accepted, as
+    /// with `unregistered_names`, so that lines using them are kept too.
+    pub fn retain_raw(mut self, retain: bool) -> Self {
+        self.retain_raw = retain;
+        self
+    }
+
+    fn allows_unregistered_names(&self) -> bool {
+        self.unregistered_names || self.retain_raw
     }
 }
 
+/// The length of the line break that makes up a blank line at the start of `buf`, if there is one: one
+/// not followed by a folded line, and CRLF unless `bare_line_breaks`.
+fn blank_line(buf: &[u8], bare_line_breaks: bool) -> Option<usize> {
+    let n = match buf {
+        [b'\r', b'\n', ..] => 2,
+        [b'\r', _, ..] | [b'\n', ..] if bare_line_breaks => 1,
+        _ => return None,
+    };
+    match buf.get(n) {
+        Some(b' ') | Some(b'\t') => None,
+        _ => Some(n),
+    }
+}
+
+fn latin1(s: &[u8]) -> String {
+    s.iter().map(|&c| char::from(c)).collect()
+}
+
 pub struct Lexer<S> {
     stream: S,
+    options: ParserOptions,
     content: ContentLine,
     ident_buf: Vec<u8>,
     line: usize,
+    /// Octets consumed for the current content line, if retaining them.
+    raw: Option<Vec<u8>>,
+    /// The line break ending the current content line has been read (and is what `peek` returns).
+    eol: bool,
+    /// Where the line break ending the current content line is.
+    eol_pos: Position,
+    /// Octets consumed from the stream.
+    offset: usize,
+    /// Offset of the start of the current physical line.
+    line_start: usize,
+    /// Start of the name, parameter or value being read, or where a line break that is not allowed is.
+    mark: Position,
+    name_span: Span,
+    value_span: Span,
+    warnings: Vec<Warning>,
+    /// An unrecoverable error has been returned by the iterator.
+    failed: bool,
 }
 
 impl<S: BufRead> Lexer<S> {
+    /// A lexer with `ParserOptions::default()`, which is not `ParserOptions::strict()`: bare line breaks
+    /// are accepted, and malformed lines skipped when reading components. Use `with_options` for strict
+    /// RFC 5545 conformance, or `ParserOptions::lenient()`.
     pub fn new(stream: S) -> Self {
-        let content = ContentLine { name: Property::End, params: Vec::new(), value: String::new(), line: 0, num_params: 0 };
+        Self::with_options(stream, ParserOptions::default())
+    }
+
+    pub fn with_options(stream: S, options: ParserOptions) -> Self {
+        let content = ContentLine::default();
         let ident_buf = Vec::new();
         let line = 1;
-        Self { stream, content, ident_buf, line }
+        Self {
+            stream, options, content, ident_buf, line,
+            raw: if options.retain_raw { Some(Vec::new()) } else { None },
+            eol: false,
+            eol_pos: Position::default(),
+            offset: 0,
+            line_start: 0,
+            mark: Position::default(),
+            name_span: Span::default(),
+            value_span: Span::default(),
+            warnings: Vec::new(),
+            failed: false,
+        }
+    }
+
+    /// Keep the original text of each content line; equivalent to `ParserOptions::retain_raw`.
+    pub fn retain_raw(mut self) -> Self {
+        self.options.retain_raw = true;
+        self.raw = Some(Vec::new());
+        self
+    }
+
+    pub fn options(&self) -> &ParserOptions {
+        &self.options
+    }
+
+    /// Warnings about the input read so far. They accumulate until taken with `take_warnings`.
+    pub fn warnings(&self) -> &[Warning] {
+        &self.warnings
+    }
+
+    pub fn take_warnings(&mut self) -> Vec<Warning> {
+        std::mem::take(&mut self.warnings)
+    }
+
+    fn warn(&mut self, code: WarningCode, span: Span, message: String) {
+        self.warnings.push(Warning::new(code, span, message));
     }
 
+    /// Read the next content line. Blank lines are skipped. After a recoverable error (see
+    /// `Error::is_recoverable`), the rest of the offending line has been skipped, and lexing can
+    /// continue.
     pub fn lex_content_line(&mut self) -> Result<Option<&ContentLine>> {
-        let line = self.line;
-        if self.stream.fill_buf().map_err(|e| Bad::Io(e).at(line))?.is_empty() {
-            return Ok(None);
+        if let Some(raw) = &mut self.raw {
+            raw.clear();
         }
+        let pos = loop {
+            self.eol = false;
+            let pos = self.position();
+            if self.stream.fill_buf().map_err(|e| Bad::Io(e).at(Span::at(pos)))?.is_empty() {
+                return Ok(None);
+            }
+            match self.peek() {
+                Ok(b'\n') => continue,
+                Ok(_) => break pos,
+                Err(e) => return Err(e.at(Span::at(pos))),
+            }
+        };
         // Take buffers, operate, restore buffers (even if error), return result.
-        let mut content = std::mem::replace(&mut self.content, Default::default());
-        let mut ident_buf = std::mem::replace(&mut self.ident_buf, Default::default());
-        let mut value_buf = std::mem::replace(&mut content.value, Default::default()).into_bytes();
+        let mut content = std::mem::take(&mut self.content);
+        let mut ident_buf = std::mem::take(&mut self.ident_buf);
+        let mut value_buf = std::mem::take(&mut content.value).into_bytes();
         let result = self.do_lex_content_line(&mut ident_buf, &mut value_buf, &mut content.params);
-        let value = match String::from_utf8(value_buf) {
-            Ok(k) => k,
+        let (value, encoding) = match String::from_utf8(value_buf) {
+            Ok(k) => (k, Ok(())),
+            Err(e) if self.options.latin1_fallback && result.is_ok() => {
+                self.warn(WarningCode::Latin1, self.value_span, "value is not UTF-8; decoded as Latin-1".to_owned());
+                (latin1(e.as_bytes()), Ok(()))
+            }
             Err(e) => {
+                let error = e.utf8_error();
                 let mut buf = e.into_bytes();
                 buf.clear();
-                String::from_utf8(buf).unwrap()
+                (String::from_utf8(buf).unwrap_or_default(), Err(Bad::Encoding(error)))
             }
         };
         content.value = value;
         self.content = content;
         self.ident_buf = ident_buf;
-        match result {
+        match result.and_then(|ok| encoding.map(|()| ok)) {
             Ok((num_params, name)) => {
                 self.content.name = name;
                 self.content.num_params = num_params;
-                trace!("lex {}: {:?}", line, self.content);
+                self.content.raw_value = None;
+                if self.content.value_type() == ValueType::Text {
+                    if let Some(value) = unescape_text(&self.content.value) {
+                        self.content.raw_value = Some(std::mem::replace(&mut self.content.value, value));
+                    }
+                }
+                self.content.span = Span::new(pos, self.position());
+                self.content.name_span = self.name_span;
+                self.content.value_span = self.value_span;
+                if self.raw.is_some() {
+                    self.skip_blank_lines();
+                }
+                self.content.raw.clone_from(&self.raw);
+                trace!("lex {}: {:?}", pos, self.content);
                 Ok(Some(&self.content))
             }
             Err(e) => {
+                let at = match e {
+                    Bad::Property { .. } => self.name_span,
+                    Bad::Encoding(_) | Bad::Param(_) => Span::new(self.mark, self.position()),
+                    Bad::LineBreak => Span::at(self.mark),
+                    Bad::Io(_) | Bad::Unexpected { .. } => Span::at(self.position()),
+                };
                 if !e.is_unrecoverable() {
-                    // TODO: run out the rest of the line...
+                    if let Err(e) = self.skip_line() {
+                        return Err(e.at(Span::at(self.position())));
+                    }
                 }
-                Err(e.at(line))
+                Err(e.at(at))
             }
         }
     }
 
     fn do_lex_content_line(&mut self, ident_buf: &mut Vec<u8>, value_buf: &mut Vec<u8>, params: &mut Vec<Param>) -> Maybe<(usize, Property)> {
+        self.mark = self.position();
         self.read_identifier(ident_buf)?;
-        let name = Property::from_bytes(ident_buf).map_err(|e| e.to_owned());
+        self.name_span = Span::new(self.mark, self.position());
+        self.normalize_case(ident_buf);
+        let name = match Property::from_bytes(ident_buf) {
+            Ok(name) => Ok(name),
+            Err(token) if self.options.allows_unregistered_names() && !token.is_empty() => {
+                let token = String::from_utf8_lossy(token).into_owned();
+                self.warn(WarningCode::UnregisteredName, self.name_span, format!("unregistered property {}", token));
+                Ok(Property::Other(token))
+            }
+            Err(e) => Err(e.to_owned()),
+        };
         let num_params = self.read_params(params, ident_buf)?;
         self.read_value(value_buf)?;
         let name = name.map_err(|e|
             Bad::Property {
-                name: String::from_utf8(e).unwrap(),
+                name: String::from_utf8_lossy(&e).into_owned(),
                 params: params[..num_params].to_owned(),
-                value: String::from_utf8(value_buf.clone()).expect("non-utf8 value in unknown property type")
+                value: String::from_utf8_lossy(value_buf).into_owned(),
             })?;
         Ok((num_params, name))
     }
 
+    /// Current (physical) line number in the input.
+    pub fn line(&self) -> usize {
+        self.line
+    }
+
+    /// Current position in the input.
+    pub fn position(&self) -> Position {
+        if self.eol {
+            return self.eol_pos;
+        }
+        Position { line: self.line, column: self.offset - self.line_start + 1, offset: self.offset }
+    }
+
     pub fn finish(self) -> S {
         self.stream
     }
+
+    /// Iterate over the remaining content lines as owned values.
+    pub fn content_lines(&mut self) -> ContentLines<'_, S> {
+        ContentLines { lexer: self }
+    }
+}
+
+/// Content lines as owned values. Recoverable errors are yielded and lexing continues; iteration ends after
+/// an unrecoverable one.
+pub struct ContentLines<'l, S> {
+    lexer: &'l mut Lexer<S>,
+}
+impl<S: BufRead> Iterator for ContentLines<'_, S> {
+    type Item = Result<ContentLine>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        let lexer = &mut *self.lexer;
+        if lexer.failed {
+            return None;
+        }
+        match lexer.lex_content_line() {
+            Ok(Some(_)) => {
+                let mut content = std::mem::take(&mut lexer.content);
+                content.params.truncate(content.num_params);
+                Some(Ok(content))
+            }
+            Ok(None) => None,
+            Err(e) => {
+                lexer.failed = !e.is_recoverable();
+                Some(Err(e))
+            }
+        }
+    }
 }
 
 impl<S: BufRead> Lexer<S> {
-    /// Get the next octet, handling "unfolding" and normalization of raw line breaks from CRLF to LF.
+    /// Consume `n` octets that have been peeked.
+    fn consume(&mut self, n: usize) {
+        if let Some(raw) = &mut self.raw {
+            if let Ok(buf) = self.stream.fill_buf() {
+                raw.extend_from_slice(&buf[..n.min(buf.len())]);
+            }
+        }
+        self.stream.consume(n);
+        self.offset += n;
+    }
+
+    /// Consume the octet returned by `peek`.
+    fn advance(&mut self) {
+        if !self.eol {
+            self.consume(1);
+        }
+    }
+
+    /// Get the next octet, handling "unfolding" and normalization of raw line breaks (CRLF, or a bare
+    /// CR or LF) to LF. The end of the input also ends a line.
     fn peek(&mut self) -> Maybe<u8> {
+        if self.eol {
+            return Ok(b'\n');
+        }
         Ok(loop {
-            let c = *self.stream.fill_buf().map_err(Bad::Io)?.get(0).ok_or(Bad::Eof)?;
+            let c = match self.stream.fill_buf().map_err(Bad::Io)?.first() {
+                Some(&c) => c,
+                None => {
+                    self.eol_pos = self.position();
+                    self.eol = true;
+                    break b'\n';
+                }
+            };
             match c {
-                b'\r' => {
-                    self.stream.consume(1);
-                    let c = *self.stream.fill_buf().map_err(Bad::Io)?.get(0).ok_or(Bad::Eof)?;
-                    self.stream.consume(1);
-                    match c {
-                        b'\n' => {
-                            self.line += 1;
-                            let c = self.stream.fill_buf().map_err(Bad::Io)?.get(0);
-                            match c {
-                                Some(b' ') | Some(b'\t') => {
-                                    self.stream.consume(1);
-                                    continue;
-                                }
-                                _ => break b'\n',
-                            }
+                b'\r' | b'\n' => {
+                    let at = self.position();
+                    self.consume(1);
+                    let crlf = c == b'\r' && self.stream.fill_buf().map_err(Bad::Io)?.first() == Some(&b'\n');
+                    if crlf {
+                        self.consume(1);
+                    }
+                    self.line += 1;
+                    self.line_start = self.offset;
+                    let folded = matches!(self.stream.fill_buf().map_err(Bad::Io)?.first(), Some(b' ') | Some(b'\t'));
+                    if folded {
+                        self.consume(1);
+                    } else {
+                        self.eol = true;
+                        self.eol_pos = at;
+                    }
+                    if !crlf {
+                        if !self.options.bare_line_breaks {
+                            self.mark = at;
+                            return Err(Bad::LineBreak);
                         }
-                        c => break c,
+                        self.warn(WarningCode::BareLineBreak, Span::at(at), "line break is not CRLF".to_owned());
+                    }
+                    if !folded {
+                        break b'\n';
                     }
                 }
                 c => break c,
@@ -348,28 +1120,61 @@ impl<S: BufRead> Lexer<S> {
         })
     }
 
-    fn read_identifier(&mut self, ident_buf: &mut Vec<u8>) -> Maybe<()> {
-        ident_buf.clear();
-        Ok(loop {
-            match self.peek()? {
-                c @ b'-' | c @ b'A'..=b'Z' | c @ b'a'..=b'z' | c @ b'0'..=b'9' => {
-                    ident_buf.push(c);
-                    self.stream.consume(1);
-                }
-                _ => break,
+    /// Convert a lowercase name to uppercase, if allowed.
+    fn normalize_case(&mut self, ident_buf: &mut [u8]) {
+        if self.options.lowercase_names && ident_buf.iter().any(u8::is_ascii_lowercase) {
+            let span = Span::new(self.mark, self.position());
+            self.warn(WarningCode::LowercaseName, span, format!("lowercase name {}", String::from_utf8_lossy(ident_buf)));
+            ident_buf.make_ascii_uppercase();
+        }
+    }
+
+    fn check_single_value(&mut self, param: &Param) {
+        self.warnings.extend(check_single_value(&param.name, param.values.len(), param.span));
+    }
+
+    /// Consume the blank lines that follow the current content line. A blank line that cannot be
+    /// recognised yet (such as a bare CR at the end of the buffer) is left to be skipped when the next
+    /// content line is read.
+    fn skip_blank_lines(&mut self) {
+        loop {
+            let n = match self.stream.fill_buf() {
+                Ok(buf) => match blank_line(buf, self.options.bare_line_breaks) {
+                    Some(n) => n,
+                    None => return,
+                },
+                Err(_) => return,
+            };
+            let at = Position { line: self.line, column: self.offset - self.line_start + 1, offset: self.offset };
+            if n == 1 {
+                self.warn(WarningCode::BareLineBreak, Span::at(at), "line break is not CRLF".to_owned());
             }
-        })
+            self.consume(n);
+            self.line += 1;
+            self.line_start = self.offset;
+        }
     }
 
-    fn read_escaped(&mut self) -> Maybe<u8> {
-        self.stream.consume(1);
-        Ok(match self.peek()? {
-            b'n' => b'\n',
-            c => {
-                self.stream.consume(1);
-                c
+    /// Skip the remainder of the current content line.
+    fn skip_line(&mut self) -> Maybe<()> {
+        loop {
+            match self.peek() {
+                Ok(b'\n') => return Ok(()),
+                Ok(_) => self.advance(),
+                // The line is in error already; other line breaks in it that are not allowed are not reported.
+                Err(Bad::LineBreak) => {}
+                Err(e) => return Err(e),
             }
-        })
+        }
+    }
+
+    fn read_identifier(&mut self, ident_buf: &mut Vec<u8>) -> Maybe<()> {
+        ident_buf.clear();
+        while let c @ b'-' | c @ b'A'..=b'Z' | c @ b'a'..=b'z' | c @ b'0'..=b'9' = self.peek()? {
+            ident_buf.push(c);
+            self.advance();
+        }
+        Ok(())
     }
 
     fn read_quoted(&mut self) -> Maybe<Vec<u8>> {
@@ -377,10 +1182,10 @@ impl<S: BufRead> Lexer<S> {
         Ok(loop {
             match self.peek()? {
                 b'"' => break param_value,
-                b'\\' => param_value.push(self.read_escaped()?),
+                b'\n' => return Err(Bad::Unexpected { found: b'\n', expected: "closing DQUOTE" }),
                 c => {
                     param_value.push(c);
-                    self.stream.consume(1);
+                    self.advance();
                 }
             }
         })
@@ -388,25 +1193,33 @@ impl<S: BufRead> Lexer<S> {
 
     fn read_param_value(&mut self) -> Maybe<String> {
         let param_value = if self.peek()? == b'"' {
-            self.stream.consume(1);
+            self.advance();
             let param_value = self.read_quoted()?;
-            self.stream.consume(1);
+            self.advance();
             param_value
         } else {
             let mut param_value = Vec::new();
             loop {
                 match self.peek()? {
-                    b',' | b';' | b':' => break,
-                    b'\\' => param_value.push(self.read_escaped()?),
+                    b',' | b';' | b':' | b'\n' => break,
                     c => {
                         param_value.push(c);
-                        self.stream.consume(1);
+                        self.advance();
                     }
                 }
             }
             param_value
         };
-        String::from_utf8(param_value).map_err(Bad::Encoding)
+        let param_value = decode_carets(&param_value).unwrap_or(param_value);
+        match String::from_utf8(param_value) {
+            Ok(value) => Ok(value),
+            Err(e) if self.options.latin1_fallback => {
+                let span = Span::new(self.mark, self.position());
+                self.warn(WarningCode::Latin1, span, "parameter value is not UTF-8; decoded as Latin-1".to_owned());
+                Ok(latin1(e.as_bytes()))
+            }
+            Err(e) => Err(Bad::Encoding(e.utf8_error())),
+        }
     }
 
     fn read_params(&mut self, params: &mut Vec<Param>, ident_buf: &mut Vec<u8>) -> Maybe<usize> {
@@ -414,37 +1227,62 @@ impl<S: BufRead> Lexer<S> {
         params.clear();
         'params: loop {
             let c = self.peek()?;
-            self.stream.consume(1);
             match c {
                 b';' => {
+                    self.advance();
+                    self.mark = self.position();
                     self.read_identifier(ident_buf)?;
-                    let name = ParamName::from_bytes(ident_buf).map_err(|e| Bad::Param(e.to_owned()))?;
+                    self.normalize_case(ident_buf);
+                    let name = match ParamName::from_bytes(ident_buf) {
+                        Ok(name) => name,
+                        Err(token) if self.options.allows_unregistered_names() && !token.is_empty() => {
+                            let token = String::from_utf8_lossy(token).into_owned();
+                            let span = Span::new(self.mark, self.position());
+                            self.warn(WarningCode::UnregisteredName, span, format!("unregistered parameter {}", token));
+                            ParamName::Other(token)
+                        }
+                        Err(e) => return Err(Bad::Param(e.to_owned())),
+                    };
                     if let Some(param) = params.get_mut(i) {
                         param.name = name;
                         param.values.clear();
                     } else {
-                        params.push(Param { name, values: Vec::new() });
+                        params.push(Param::new(name, Vec::new()));
                     }
                     let c = self.peek()?;
-                    assert_eq!(c, b'=', "Illegal byte in param name. Recovery from this is unimplemented."); // FIXME
-                    self.stream.consume(1);
+                    if c != b'=' {
+                        return Err(Bad::Unexpected { found: c, expected: "'=' after parameter name" });
+                    }
+                    self.advance();
                     'param_values: loop {
                         let param_value = self.read_param_value()?;
                         params[i].values.push(param_value);
+                        params[i].span = Span::new(self.mark, self.position());
                         match self.peek()? {
-                            b',' => continue,
-                            b';' => break 'param_values,
+                            b',' => {
+                                self.advance();
+                                continue;
+                            }
+                            b';' => {
+                                self.check_single_value(&params[i]);
+                                break 'param_values;
+                            }
                             b':' => {
-                                self.stream.consume(1);
+                                self.check_single_value(&params[i]);
+                                self.advance();
+                                i += 1;
                                 break 'params;
                             }
-                            _ => unreachable!(),
+                            c => return Err(Bad::Unexpected { found: c, expected: "',', ';' or ':' after parameter value" }),
                         }
                     }
                     i += 1;
                 }
-                b':' => break 'params,
-                _ => unreachable!(),
+                b':' => {
+                    self.advance();
+                    break 'params;
+                }
+                c => return Err(Bad::Unexpected { found: c, expected: "';' or ':' after name" }),
             }
         }
         Ok(i)
@@ -452,19 +1290,195 @@ impl<S: BufRead> Lexer<S> {
 
     fn read_value(&mut self, value_buf: &mut Vec<u8>) -> Maybe<()> {
         value_buf.clear();
+        self.mark = self.position();
         loop {
             match self.peek()? {
                 b'\n' => break,
-                b'\\' => {
-                    let c = self.read_escaped()?;
-                    value_buf.push(c);
-                }
                 c => {
                     value_buf.push(c);
-                    self.stream.consume(1);
+                    self.advance();
                 }
             }
         }
+        self.value_span = Span::new(self.mark, self.position());
         Ok(())
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Each content line or error, with the name of each line.
+    fn lex(input: &[u8], options: ParserOptions) -> Vec<std::result::Result<String, ErrorKind>> {
+        let mut lexer = Lexer::with_options(input, options);
+        lexer.content_lines().map(|r| r.map(|coli| coli.name().as_str().to_owned()).map_err(|e| e.kind())).collect()
+    }
+
+    #[test]
+    fn recovery() {
+        let input = b"SUMMARY;=x:y\r\nUID:1\r\nSUMMARY;CN:y\r\nUID:2\r\nATTENDEE;CN=\"x:y\r\nUID:3\r\nNOT-A-PROPERTY:x\r\n\
+                      UID:4\r\n:x\r\nUID:5\r\nSUMMARY\r\nUID:6\r\nDESCRIPTION:\xff\r\nUID:7\r\n";
+        let lines = lex(input, ParserOptions::strict());
+        assert_eq!(lines.len(), 14);
+        for (i, pair) in lines.chunks(2).enumerate() {
+            assert!(pair[0].is_err(), "{:?}", pair);
+            assert_eq!(pair[1].as_deref(), Ok("UID"), "line {}", 2 * i + 2);
+        }
+        assert_eq!(lines[6], Err(ErrorKind::UnknownProperty));
+        assert_eq!(lines[12], Err(ErrorKind::Encoding));
+    }
+
+    #[test]
+    fn recovery_across_folds() {
+        let input = b"SUMMARY;CN\r\n =x;\r\n :y\r\nUID:1\r\n";
+        let mut lexer = Lexer::new(&input[..]);
+        let error = lexer.lex_content_line().unwrap_err();
+        assert!(error.is_recoverable());
+        assert_eq!(error.line(), 2);
+        assert_eq!(lexer.lex_content_line().unwrap().unwrap().value(), "1");
+        assert_eq!(lexer.line(), 5);
+    }
+
+    #[test]
+    fn no_panics() {
+        // Pseudo-random input over the characters that matter to the lexer.
+        const ALPHABET: &[u8] = b"\r\n \t;:,=\"^\\ABX-az\xc3\xa9\xff";
+        let mut seed = 1u32;
+        for _ in 0..2000 {
+            let input: Vec<u8> = (0..40).map(|_| {
+                seed = seed.wrapping_mul(1_103_515_245).wrapping_add(12345);
+                ALPHABET[(seed >> 16) as usize % ALPHABET.len()]
+            }).collect();
+            for options in [ParserOptions::strict(), ParserOptions::lenient().retain_raw(true)] {
+                let mut lexer = Lexer::with_options(&input[..], options);
+                assert!(lexer.content_lines().count() <= input.len());
+                let mut lexer = slice::Lexer::with_options(&input, options);
+                assert!(lexer.content_lines().count() <= input.len());
+            }
+        }
+    }
+
+    #[test]
+    fn content_lines() {
+        let input = b"ATTENDEE;CN=a;RSVP=TRUE:mailto:a@x\r\nSUMMARY;CN:x\r\nATTENDEE:mailto:b@x\r\nUID:1\r\n";
+        let mut lexer = Lexer::new(&input[..]);
+        let lines: Vec<_> = lexer.content_lines().filter_map(|r| r.ok()).collect();
+        assert_eq!(lines.iter().map(|coli| coli.value()).collect::<Vec<_>>(), ["mailto:a@x", "mailto:b@x", "1"]);
+        // Each line has only its own parameters.
+        assert_eq!(lines[0].params().count(), 2);
+        assert_eq!(lines[1].params().count(), 0);
+        assert!(lexer.content_lines().next().is_none());
+
+        // Iteration ends after an unrecoverable error.
+        let mut lexer = Lexer::new(std::io::BufReader::new(Failing));
+        let lines: Vec<_> = lexer.content_lines().collect();
+        assert_eq!(lines.len(), 1);
+        assert_eq!(lines[0].as_ref().unwrap_err().kind(), ErrorKind::Io);
+    }
+
+    #[test]
+    fn parameter_carets() {
+        let input = b"ATTENDEE;CN=\"Say ^'hi^' ^^ ^n\";LANGUAGE=a\\b^x^;DIR=\"^^n\":mailto:x\r\n";
+        let mut lexer = Lexer::new(&input[..]);
+        let coli = lexer.lex_content_line().unwrap().unwrap();
+        assert_eq!(coli.value_of(IanaParam::Cn), Some("Say \"hi\" ^ \n"));
+        // Backslashes are not escapes in parameter values, nor is a circumflex before anything else.
+        assert_eq!(coli.value_of(IanaParam::Language), Some("a\\b^x^"));
+        assert_eq!(coli.value_of(IanaParam::Dir), Some("^n"));
+
+        // Values are encoded when written, and read back the same.
+        let mut writer = writer::Writer::new(Vec::new());
+        writer.write_content_line(coli).unwrap();
+        let written = writer.finish();
+        assert_eq!(&written[..39], &b"ATTENDEE;CN=Say ^'hi^' ^^ ^n;LANGUAGE=a"[..]);
+        let params: Vec<_> = coli.params().cloned().collect();
+        let mut lexer = Lexer::new(&written[..]);
+        assert!(lexer.lex_content_line().unwrap().unwrap().params().eq(&params));
+    }
+
+    struct Failing;
+    impl std::io::Read for Failing {
+        fn read(&mut self, _: &mut [u8]) -> std::io::Result<usize> {
+            Err(std::io::Error::other("disk on fire"))
+        }
+    }
+
+    #[test]
+    fn errors() {
+        use std::error::Error as _;
+        let mut lexer = Lexer::new(&b"UID:1\r\nSUMMARY;CN:x\r\n"[..]);
+        lexer.lex_content_line().unwrap();
+        let e = lexer.lex_content_line().unwrap_err();
+        assert_eq!(e.kind(), ErrorKind::Syntax);
+        assert_eq!((e.line(), e.position().column(), e.position().offset()), (2, 11, 17));
+        assert_eq!(e.to_string(), "line 2, column 11: unexpected ':', expected '=' after parameter name");
+        assert!(e.source().is_none());
+
+        let e = Lexer::new(&b"SUMMARY:\xff\r\n"[..]).lex_content_line().unwrap_err();
+        assert_eq!(e.kind(), ErrorKind::Encoding);
+        assert!(e.source().is_some());
+
+        let mut lexer = Lexer::new(std::io::BufReader::new(Failing));
+        let e = lexer.lex_content_line().unwrap_err();
+        assert_eq!(e.kind(), ErrorKind::Io);
+        assert!(!e.is_recoverable());
+        assert_eq!(e.source().unwrap().to_string(), "disk on fire");
+
+        // Errors from later stages keep the lexer's error as their source.
+        let e = component::Component::parse(&mut Lexer::new(std::io::BufReader::new(Failing))).unwrap_err();
+        assert_eq!(e.kind(), ErrorKind::Io);
+        assert!(e.source().is_some());
+        let e = component::Component::parse(&mut Lexer::new(&b"BEGIN:A\r\nEND:B\r\n"[..])).unwrap_err();
+        assert_eq!(e.kind(), ErrorKind::Nesting);
+        assert_eq!(e.line(), 2);
+    }
+
+    #[test]
+    fn line_breaks() {
+        let input = b"BEGIN:VCALENDAR\nVERSION:2.0\nEND:VCALENDAR\n";
+        let mut lexer = Lexer::new(&input[..]);
+        assert_eq!(component::Component::parse_all(&mut lexer).unwrap().len(), 1);
+        assert_eq!(lexer.warnings().len(), 3);
+        assert!(lexer.warnings().iter().all(|w| w.code() == WarningCode::BareLineBreak));
+
+        let lines = lex(input, ParserOptions::strict());
+        assert_eq!(lines, [Err(ErrorKind::Syntax), Err(ErrorKind::Syntax), Err(ErrorKind::Syntax)]);
+    }
+
+    #[test]
+    fn leniencies() {
+        let check = |input: &[u8], options: ParserOptions, code, name| {
+            assert!(lex(input, ParserOptions::strict())[0].is_err());
+            let mut lexer = Lexer::with_options(input, options);
+            assert_eq!(lexer.lex_content_line().unwrap().unwrap().name().as_str(), name);
+            assert_eq!(lexer.warnings().iter().map(Warning::code).collect::<Vec<_>>(), [code]);
+        };
+        let strict = ParserOptions::strict();
+        check(b"FOO-BAR:x\r\n", strict.unregistered_names(true), WarningCode::UnregisteredName, "FOO-BAR");
+        check(b"summary:x\r\n", strict.lowercase_names(true), WarningCode::LowercaseName, "SUMMARY");
+        check(b"SUMMARY:caf\xe9\r\n", strict.latin1_fallback(true), WarningCode::Latin1, "SUMMARY");
+        let mut lexer = Lexer::with_options(&b"SUMMARY:caf\xe9\r\n"[..], ParserOptions::lenient());
+        assert_eq!(lexer.lex_content_line().unwrap().unwrap().value(), "café");
+    }
+
+    #[test]
+    fn default_options() {
+        let default = ParserOptions::strict().bare_line_breaks(true).skip_malformed_lines(true);
+        assert_eq!(ParserOptions::default(), default);
+        assert_eq!(Lexer::new(&b""[..]).options(), &default);
+        assert_eq!(slice::Lexer::new(b"").options(), &default);
+        assert_ne!(default, ParserOptions::strict());
+    }
+
+    #[test]
+    fn multiple_values() {
+        let mut lexer = Lexer::new(&b"UID:1\r\nATTENDEE;CN=a,b;MEMBER=c,d:mailto:x\r\n"[..]);
+        while lexer.lex_content_line().unwrap().is_some() {}
+        let warnings = lexer.take_warnings();
+        assert_eq!(warnings.len(), 1);
+        assert_eq!((warnings[0].code(), warnings[0].line()), (WarningCode::MultipleValues, 2));
+        assert_eq!(warnings[0].message(), "CN has 2 values; only the first is used");
+        assert!(lexer.warnings().is_empty());
+    }
+}
@@ -0,0 +1,153 @@
+//! Values of enumerated parameters, and typed accessors for them on `ContentLine`.
+
+use crate::{define_identifier_set, ContentLine, IanaParam};
+use std::str::FromStr;
+
+/// An enumerated parameter's value: one from the initial registry, or any other (an x-name or
+/// iana-token), as `ComponentName`.
+macro_rules! define_param_value {
+    ( $Name:ident, $Iana:ident, $( $Tok:ident, $tok_name:expr ),* $(,)? ) => {
+        define_identifier_set!($Iana, $( $Tok, $tok_name, )*);
+
+        #[derive(Debug, PartialEq, Eq, Clone)]
+        pub enum $Name {
+            Iana($Iana),
+            Other(String),
+        }
+        impl $Name {
+            /// Registered values are matched case-insensitively.
+            pub(crate) fn from_value(s: &str) -> Self {
+                match $Iana::from_str(&s.to_ascii_uppercase()) {
+                    Ok(iana) => $Name::Iana(iana),
+                    Err(()) => $Name::Other(s.to_owned()),
+                }
+            }
+
+            pub fn as_str(&self) -> &str {
+                match self {
+                    $Name::Iana(iana) => iana.as_str(),
+                    $Name::Other(s) => s,
+                }
+            }
+        }
+        impl From<$Iana> for $Name {
+            fn from(iana: $Iana) -> Self {
+                $Name::Iana(iana)
+            }
+        }
+        impl std::fmt::Display for $Name {
+            fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+                f.write_str(self.as_str())
+            }
+        }
+    };
+}
+
+define_param_value!(Cutype, IanaCutype,
+    Individual, b"INDIVIDUAL",
+    Group,      b"GROUP",
+    Resource,   b"RESOURCE",
+    Room,       b"ROOM",
+    Unknown,    b"UNKNOWN",
+);
+
+define_param_value!(Role, IanaRole,
+    Chair,          b"CHAIR",
+    ReqParticipant, b"REQ-PARTICIPANT",
+    OptParticipant, b"OPT-PARTICIPANT",
+    NonParticipant, b"NON-PARTICIPANT",
+);
+
+define_param_value!(Partstat, IanaPartstat,
+    NeedsAction, b"NEEDS-ACTION",
+    Accepted,    b"ACCEPTED",
+    Declined,    b"DECLINED",
+    Tentative,   b"TENTATIVE",
+    Delegated,   b"DELEGATED",
+    Completed,   b"COMPLETED",
+    InProcess,   b"IN-PROCESS",
+);
+
+define_param_value!(Reltype, IanaReltype,
+    Parent,  b"PARENT",
+    Child,   b"CHILD",
+    Sibling, b"SIBLING",
+);
+
+define_param_value!(Fbtype, IanaFbtype,
+    Free,            b"FREE",
+    Busy,            b"BUSY",
+    BusyUnavailable, b"BUSY-UNAVAILABLE",
+    BusyTentative,   b"BUSY-TENTATIVE",
+);
+
+define_param_value!(Related, IanaRelated,
+    Start, b"START",
+    End,   b"END",
+);
+
+define_param_value!(Range, IanaRange,
+    ThisAndFuture, b"THISANDFUTURE",
+);
+
+impl ContentLine {
+    /// The CUTYPE parameter; if absent, INDIVIDUAL applies.
+    pub fn cutype(&self) -> Option<Cutype> {
+        self.value_of(IanaParam::Cutype).map(Cutype::from_value)
+    }
+
+    /// The ROLE parameter; if absent, REQ-PARTICIPANT applies.
+    pub fn role(&self) -> Option<Role> {
+        self.value_of(IanaParam::Role).map(Role::from_value)
+    }
+
+    /// The PARTSTAT parameter; if absent, NEEDS-ACTION applies.
+    pub fn partstat(&self) -> Option<Partstat> {
+        self.value_of(IanaParam::Partstat).map(Partstat::from_value)
+    }
+
+    /// The RELTYPE parameter; if absent, PARENT applies.
+    pub fn reltype(&self) -> Option<Reltype> {
+        self.value_of(IanaParam::Reltype).map(Reltype::from_value)
+    }
+
+    /// The FBTYPE parameter; if absent, BUSY applies.
+    pub fn fbtype(&self) -> Option<Fbtype> {
+        self.value_of(IanaParam::Fbtype).map(Fbtype::from_value)
+    }
+
+    /// The RELATED parameter of a TRIGGER; if absent, START applies.
+    pub fn related(&self) -> Option<Related> {
+        self.value_of(IanaParam::Related).map(Related::from_value)
+    }
+
+    /// The RANGE parameter of a RECURRENCE-ID.
+    pub fn range(&self) -> Option<Range> {
+        self.value_of(IanaParam::Range).map(Range::from_value)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::Lexer;
+
+    #[test]
+    fn accessors() {
+        let input = b"ATTENDEE;CUTYPE=room;ROLE=X-OBSERVER;PARTSTAT=Accepted;RANGE=thisandprior:mailto:x\r\n";
+        let mut lexer = Lexer::new(&input[..]);
+        let coli = lexer.lex_content_line().unwrap().unwrap();
+        assert_eq!(coli.cutype(), Some(Cutype::Iana(IanaCutype::Room)));
+        assert_eq!(coli.role(), Some(Role::Other("X-OBSERVER".to_owned())));
+        assert_eq!(coli.partstat(), Some(Partstat::Iana(IanaPartstat::Accepted)));
+        // Other values are kept as they were written.
+        assert_eq!(coli.range(), Some(Range::Other("thisandprior".to_owned())));
+        assert_eq!(coli.reltype(), None);
+        assert_eq!(coli.related(), None);
+
+        assert_eq!(Cutype::from(IanaCutype::Room).to_string(), "ROOM");
+        assert_eq!(Role::Other("X-OBSERVER".to_owned()).to_string(), "X-OBSERVER");
+        assert_eq!(Range::from_value("ThisAndFuture"), Range::Iana(IanaRange::ThisAndFuture));
+        assert_eq!(Fbtype::from_value("busy-tentative").as_str(), "BUSY-TENTATIVE");
+    }
+}
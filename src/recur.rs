@@ -0,0 +1,1032 @@
+//! Expansion of recurrence rules into occurrences, per RFC 5545 section 3.3.10, and of events into
+//! their recurrence sets.
+
+use crate::param::{IanaRange, Range};
+use crate::tz::Timezones;
+use crate::vevent::{DateTime, Freq, Period, RdateValue, Rrule, Stop, Until, VEvent, Weekday, When};
+use chrono::{Datelike, Duration, NaiveDate, NaiveDateTime, NaiveTime, Timelike};
+use std::collections::VecDeque;
+use std::iter::Peekable;
+use std::convert::TryFrom;
+
+/// Occurrences are not searched for past the end of this year; this bounds the search for rules
+/// that can never match again (e.g. FREQ=YEARLY;BYMONTH=2;BYMONTHDAY=30).
+const MAX_YEAR: i32 = 9999;
+
+fn weekday_index(wday: Weekday) -> u32 {
+    match wday {
+        Weekday::Mo => 0,
+        Weekday::Tu => 1,
+        Weekday::We => 2,
+        Weekday::Th => 3,
+        Weekday::Fr => 4,
+        Weekday::Sa => 5,
+        Weekday::Su => 6,
+    }
+}
+
+fn days_in_month(year: i32, month: u32) -> u32 {
+    let (y, m) = if month == 12 { (year + 1, 1) } else { (year, month + 1) };
+    NaiveDate::from_ymd_opt(y, m, 1).unwrap().pred_opt().unwrap().day()
+}
+
+fn days_in_year(year: i32) -> u32 {
+    if NaiveDate::from_ymd_opt(year, 2, 29).is_some() { 366 } else { 365 }
+}
+
+/// Does `n` (1-based, or negative counting from the end) select position `pos` (1-based) of `len`?
+fn selects(n: i32, pos: u32, len: u32) -> bool {
+    n == pos as i32 || n == pos as i32 - len as i32 - 1
+}
+
+/// First day of week 1 of `year`: the first week, starting on `wkst`, with at least 4 days in the year.
+fn week1_start(year: i32, wkst: u32) -> NaiveDate {
+    let jan1 = NaiveDate::from_ymd_opt(year, 1, 1).unwrap();
+    let offset = (jan1.weekday().num_days_from_monday() + 7 - wkst) % 7;
+    if offset <= 3 {
+        jan1 - Duration::days(offset.into())
+    } else {
+        jan1 + Duration::days((7 - offset).into())
+    }
+}
+
+/// First day of week 1, and number of weeks, of the year being expanded by BYWEEKNO.
+type WeekNumbering = (NaiveDate, u32);
+
+/// Scope in which an ordinal BYDAY (e.g. -1SU) counts weekdays.
+#[derive(Clone, Copy, PartialEq, Eq)]
+enum Scope {
+    Month,
+    Year,
+    None,
+}
+
+/// Iterator over the occurrences of an `Rrule`, in order.
+///
+/// Created by `Rrule::occurrences`. Occurrences take the kind (DATE, UTC, local or floating) of the
+/// DTSTART they were generated from. A UTC UNTIL is compared with occurrences in UTC, resolving DTSTART's
+/// TZID as `Timezones::to_utc` does; if it cannot be resolved, they are compared by wall-clock value.
+pub struct Occurrences<'r> {
+    rule: &'r Rrule,
+    dtstart: When,
+    zones: Option<&'r Timezones>,
+    /// The UTC offset of occurrences, if known without resolving a TZID.
+    utc_offset: Option<Duration>,
+    start: NaiveDateTime,
+    interval: i64,
+    wkst: u32,
+    bymonth: Vec<u32>,
+    bymonthday: Vec<i32>,
+    byday: Vec<(u32, Option<i32>)>,
+    byhour: Vec<u32>,
+    byminute: Vec<u32>,
+    bysecond: Vec<u32>,
+    /// Number of periods (of `interval` units of `freq`) elapsed since DTSTART.
+    period: i64,
+    /// Start of the current period, for sub-daily frequencies.
+    cursor: NaiveDateTime,
+    pending: VecDeque<NaiveDateTime>,
+    /// Whether DTSTART is the first occurrence even if the rule does not generate it.
+    seeded: bool,
+    emitted: u32,
+    done: bool,
+}
+
+impl Rrule {
+    /// Iterate over the recurrences of the rule starting at `dtstart`. As RFC 5545 specifies, DTSTART
+    /// is always the first occurrence.
+    pub fn occurrences(&self, dtstart: &When) -> Occurrences<'_> {
+        Occurrences::new(self, dtstart, None)
+    }
+
+    /// Like `occurrences`, resolving the TZID of DTSTART in the calendar's timezones.
+    pub fn occurrences_in<'r>(&'r self, dtstart: &When, zones: &'r Timezones) -> Occurrences<'r> {
+        Occurrences::new(self, dtstart, Some(zones))
+    }
+}
+
+impl<'r> Occurrences<'r> {
+    fn new(rule: &'r Rrule, dtstart: &When, zones: Option<&'r Timezones>) -> Self {
+        let start = dtstart.naive();
+        let is_date = matches!(dtstart, When::Date(_));
+        let freq = rule.freq;
+        let coarser = |than: Freq| freq_rank(freq) > freq_rank(than);
+        let list = |v: &Option<Vec<u8>>| v.as_ref().map(|v| v.iter().map(|&x| u32::from(x)).collect::<Vec<_>>());
+
+        let mut bymonth = list(&rule.bymonth).unwrap_or_default();
+        let mut bymonthday = rule.bymonthday.clone().unwrap_or_default();
+        let mut byday: Vec<_> = rule.byday.iter().flatten()
+            .map(|wn| (weekday_index(wn.wday), wn.num.map(i32::from)))
+            .collect();
+        // Parts not given in the rule are taken from DTSTART, according to the frequency.
+        let no_day_parts = rule.byweekno.is_none() && rule.byyearday.is_none()
+            && rule.bymonthday.is_none() && rule.byday.is_none();
+        match freq {
+            Freq::Yearly if no_day_parts => {
+                if rule.bymonth.is_none() {
+                    bymonth = vec![start.month()];
+                }
+                bymonthday = vec![start.day() as i32];
+            }
+            Freq::Monthly if no_day_parts => bymonthday = vec![start.day() as i32],
+            Freq::Weekly if rule.byday.is_none() => byday = vec![(start.weekday().num_days_from_monday(), None)],
+            _ => (),
+        }
+        let time_part = |v: &Option<Vec<u8>>, from_start: u32, unit: Freq| {
+            if is_date {
+                vec![0]
+            } else {
+                list(v).unwrap_or_else(|| if coarser(unit) { vec![from_start] } else { Vec::new() })
+            }
+        };
+        let byhour = time_part(&rule.byhour, start.hour(), Freq::Hourly);
+        let byminute = time_part(&rule.byminute, start.minute(), Freq::Minutely);
+        let bysecond = time_part(&rule.bysecond, start.second(), Freq::Secondly);
+
+        Occurrences {
+            rule,
+            dtstart: dtstart.clone(),
+            zones,
+            utc_offset: None,
+            start,
+            interval: i64::from(rule.interval.unwrap_or(1).max(1)),
+            wkst: rule.wkst.map(weekday_index).unwrap_or(0),
+            bymonth,
+            bymonthday,
+            byday,
+            byhour,
+            byminute,
+            bysecond,
+            period: 0,
+            cursor: start,
+            pending: VecDeque::new(),
+            seeded: true,
+            emitted: 0,
+            done: false,
+        }
+    }
+
+    /// Compare a UTC UNTIL with occurrences as if they were at `offset` from UTC, as for the
+    /// observances of a VTIMEZONE.
+    pub(crate) fn at_utc_offset(mut self, offset: Duration) -> Self {
+        self.utc_offset = Some(offset);
+        self
+    }
+
+    /// Only yield DTSTART if the rule generates it, as for an EXRULE: DTSTART is not itself excluded.
+    fn unseeded(mut self) -> Self {
+        self.seeded = false;
+        self
+    }
+
+    /// Restrict the iteration to occurrences at or after `start` and before `end`.
+    pub fn between(self, start: &When, end: &When) -> impl Iterator<Item=When> + 'r {
+        let (start, end) = (start.naive(), end.naive());
+        self.skip_while(move |w| w.naive() < start).take_while(move |w| w.naive() < end)
+    }
+
+    fn scope(&self) -> Scope {
+        match self.rule.freq {
+            Freq::Monthly => Scope::Month,
+            Freq::Yearly if self.rule.byweekno.is_some() => Scope::None,
+            Freq::Yearly if self.rule.bymonth.is_some() => Scope::Month,
+            Freq::Yearly => Scope::Year,
+            _ => Scope::None,
+        }
+    }
+
+    /// Apply the day-level BYxxx rule parts that limit (or, in a larger period, expand) the set.
+    fn day_matches(&self, d: NaiveDate, scope: Scope, week1: Option<WeekNumbering>) -> bool {
+        let rule = self.rule;
+        if !self.bymonth.is_empty() && !self.bymonth.contains(&d.month()) {
+            return false;
+        }
+        if let (Some(weeks), Some((week1, nweeks))) = (&rule.byweekno, week1) {
+            let week = ((d - week1).num_days() / 7 + 1) as u32;
+            if !weeks.iter().any(|&n| selects(n, week, nweeks)) {
+                return false;
+            }
+        }
+        if let Some(days) = &rule.byyearday {
+            if !matches!(rule.freq, Freq::Monthly | Freq::Weekly)
+                && !days.iter().any(|&n| selects(n, d.ordinal(), days_in_year(d.year()))) {
+                return false;
+            }
+        }
+        if !self.bymonthday.is_empty() && rule.freq != Freq::Weekly {
+            let len = days_in_month(d.year(), d.month());
+            if !self.bymonthday.iter().any(|&n| selects(n, d.day(), len)) {
+                return false;
+            }
+        }
+        if !self.byday.is_empty() {
+            let wday = d.weekday().num_days_from_monday();
+            let matched = self.byday.iter().any(|&(w, num)| {
+                if w != wday {
+                    return false;
+                }
+                let (pos, len) = match scope {
+                    Scope::Month => (d.day(), days_in_month(d.year(), d.month())),
+                    Scope::Year => (d.ordinal(), days_in_year(d.year())),
+                    Scope::None => return true,
+                };
+                match num {
+                    None => true,
+                    Some(n) => selects(n, (pos - 1) / 7 + 1, (len - pos) / 7 + (pos - 1) / 7 + 1),
+                }
+            });
+            if !matched {
+                return false;
+            }
+        }
+        true
+    }
+
+    /// Days of the current (daily or longer) period, and the week numbering used for BYWEEKNO.
+    fn period_days(&self) -> Option<(NaiveDate, NaiveDate, Option<WeekNumbering>)> {
+        let start = self.start.date();
+        let n = self.period.checked_mul(self.interval)?;
+        Some(match self.rule.freq {
+            Freq::Yearly => {
+                let year = i32::try_from(i64::from(start.year()) + n).ok()?;
+                if year > MAX_YEAR {
+                    return None;
+                }
+                if self.rule.byweekno.is_some() {
+                    let first = week1_start(year, self.wkst);
+                    let next = week1_start(year + 1, self.wkst);
+                    let nweeks = ((next - first).num_days() / 7) as u32;
+                    (first, next, Some((first, nweeks)))
+                } else {
+                    (NaiveDate::from_ymd_opt(year, 1, 1)?, NaiveDate::from_ymd_opt(year + 1, 1, 1)?, None)
+                }
+            }
+            Freq::Monthly => {
+                let month0 = i64::from(start.year()) * 12 + i64::from(start.month0()) + n;
+                let year = i32::try_from(month0.div_euclid(12)).ok()?;
+                if year > MAX_YEAR {
+                    return None;
+                }
+                let first = NaiveDate::from_ymd_opt(year, month0.rem_euclid(12) as u32 + 1, 1)?;
+                let len = days_in_month(first.year(), first.month());
+                (first, first + Duration::days(len.into()), None)
+            }
+            Freq::Weekly => {
+                let back = (start.weekday().num_days_from_monday() + 7 - self.wkst) % 7;
+                let first = start.checked_sub_signed(Duration::days(back.into()))?
+                    .checked_add_signed(Duration::try_days(n.checked_mul(7)?)?)?;
+                if first.year() > MAX_YEAR {
+                    return None;
+                }
+                (first, first + Duration::days(7), None)
+            }
+            Freq::Daily => {
+                let first = start.checked_add_signed(Duration::try_days(n)?)?;
+                if first.year() > MAX_YEAR {
+                    return None;
+                }
+                (first, first + Duration::days(1), None)
+            }
+            Freq::Hourly | Freq::Minutely | Freq::Secondly => unreachable!(),
+        })
+    }
+
+    /// Candidate times within the current sub-daily period, advancing past days (and hours, minutes)
+    /// that cannot match.
+    fn subdaily_candidates(&mut self) -> Option<Vec<NaiveDateTime>> {
+        let step = self.interval * match self.rule.freq {
+            Freq::Hourly => 3600,
+            Freq::Minutely => 60,
+            _ => 1,
+        };
+        loop {
+            let t = self.cursor;
+            if t.year() > MAX_YEAR {
+                return None;
+            }
+            let secs = i64::from(t.num_seconds_from_midnight());
+            // Skip forward past the end of the unit (day, hour or minute) that failed to match. Returns
+            // `None` if that is out of range.
+            let skip_unit = |unit: i64| {
+                let remaining = unit - secs % unit;
+                t.checked_add_signed(Duration::try_seconds((remaining + step - 1) / step * step)?)
+            };
+            let next = t.checked_add_signed(Duration::try_seconds(step)?);
+            let scope = self.scope();
+            if !self.day_matches(t.date(), scope, None) {
+                self.cursor = skip_unit(86400)?;
+                continue;
+            }
+            if !self.byhour.is_empty() && !self.byhour.contains(&t.hour()) {
+                self.cursor = skip_unit(3600)?;
+                continue;
+            }
+            let minutes = match self.rule.freq {
+                Freq::Hourly => self.byminute.clone(),
+                _ if !self.byminute.is_empty() && !self.byminute.contains(&t.minute()) => {
+                    self.cursor = skip_unit(60)?;
+                    continue;
+                }
+                _ => vec![t.minute()],
+            };
+            let seconds = match self.rule.freq {
+                Freq::Hourly | Freq::Minutely => self.bysecond.clone(),
+                _ if !self.bysecond.is_empty() && !self.bysecond.contains(&t.second()) => {
+                    self.cursor = next?;
+                    continue;
+                }
+                _ => vec![t.second()],
+            };
+            // On overflow, this period is still searched; the next call ends the iteration.
+            self.cursor = next.unwrap_or(NaiveDateTime::MAX);
+            return Some(combine(t.date(), &[t.hour()], &minutes, &seconds));
+        }
+    }
+
+    /// Compute the occurrences of the next period, after BYSETPOS is applied. Returns `None` once
+    /// there are no more periods to search.
+    fn next_period(&mut self) -> Option<Vec<NaiveDateTime>> {
+        let mut set = match self.rule.freq {
+            Freq::Hourly | Freq::Minutely | Freq::Secondly => self.subdaily_candidates()?,
+            _ => {
+                let (first, end, week1) = self.period_days()?;
+                self.period += 1;
+                let scope = self.scope();
+                let mut set = Vec::new();
+                let mut d = first;
+                while d < end {
+                    if self.day_matches(d, scope, week1) {
+                        set.extend(combine(d, &self.byhour, &self.byminute, &self.bysecond));
+                    }
+                    d = d.succ_opt()?;
+                }
+                set
+            }
+        };
+        set.sort();
+        set.dedup();
+        if let Some(positions) = &self.rule.bysetpos {
+            let len = set.len() as u32;
+            set = set.iter().enumerate()
+                .filter(|&(i, _)| positions.iter().any(|&n| selects(n, i as u32 + 1, len)))
+                .map(|(_, &t)| t)
+                .collect();
+        }
+        Some(set)
+    }
+
+    fn past_until(&self, t: NaiveDateTime) -> bool {
+        match &self.rule.stop {
+            Some(Stop::Until(Until::Date(d))) => t.date() > *d,
+            Some(Stop::Until(Until::DateTime(DateTime::Utc(until)))) => self.to_utc(t).unwrap_or(t) > *until,
+            Some(Stop::Until(Until::DateTime(dt))) => t > dt.naive(),
+            _ => false,
+        }
+    }
+
+    /// The UTC time of the occurrence at wall-clock time `t`, if DTSTART's timezone can be resolved.
+    fn to_utc(&self, t: NaiveDateTime) -> Option<NaiveDateTime> {
+        if let Some(offset) = self.utc_offset {
+            return t.checked_sub_signed(offset);
+        }
+        let dt = match &self.dtstart {
+            When::DateTime(dt) => dt.with_naive(t),
+            When::Date(_) => return None,
+        };
+        match self.zones {
+            Some(zones) => zones.to_utc(&dt),
+            None => Timezones::new().to_utc(&dt),
+        }
+    }
+}
+
+fn freq_rank(freq: Freq) -> u8 {
+    match freq {
+        Freq::Secondly => 0,
+        Freq::Minutely => 1,
+        Freq::Hourly => 2,
+        Freq::Daily => 3,
+        Freq::Weekly => 4,
+        Freq::Monthly => 5,
+        Freq::Yearly => 6,
+    }
+}
+
+fn combine(d: NaiveDate, hours: &[u32], minutes: &[u32], seconds: &[u32]) -> Vec<NaiveDateTime> {
+    let mut out = Vec::with_capacity(hours.len() * minutes.len() * seconds.len());
+    for &h in hours {
+        for &m in minutes {
+            for &s in seconds {
+                if let Some(t) = NaiveTime::from_hms_opt(h, m, s) {
+                    out.push(d.and_time(t));
+                }
+            }
+        }
+    }
+    out
+}
+
+impl<'r> Iterator for Occurrences<'r> {
+    type Item = When;
+
+    fn next(&mut self) -> Option<When> {
+        if self.done {
+            return None;
+        }
+        if let Some(Stop::Count(count)) = self.rule.stop {
+            if self.emitted >= count {
+                self.done = true;
+                return None;
+            }
+        }
+        if self.seeded && self.emitted == 0 {
+            self.emitted = 1;
+            return Some(self.dtstart.clone());
+        }
+        loop {
+            if let Some(t) = self.pending.pop_front() {
+                if t < self.start || self.seeded && t == self.start {
+                    continue;
+                }
+                if self.past_until(t) {
+                    self.done = true;
+                    return None;
+                }
+                self.emitted += 1;
+                return Some(self.dtstart.with_naive(t));
+            }
+            match self.next_period() {
+                Some(set) => self.pending.extend(set),
+                None => {
+                    self.done = true;
+                    return None;
+                }
+            }
+        }
+    }
+}
+
+/// The complete recurrence set of an event: DTSTART, RRULE and RDATE instances, less those removed by
+/// EXDATE and EXRULE, with the overrides from other VEVENTs sharing its UID (those with a
+/// RECURRENCE-ID) applied.
+pub struct RecurrenceSet<'e> {
+    master: &'e VEvent,
+    /// Overriding events, ordered by RECURRENCE-ID.
+    overrides: Vec<&'e VEvent>,
+    zones: Option<&'e Timezones>,
+}
+
+/// One instance of a recurring event.
+#[derive(Debug)]
+pub struct Instance<'e> {
+    recurrence_id: When,
+    start: When,
+    event: &'e VEvent,
+    period: Option<&'e Period>,
+}
+impl<'e> Instance<'e> {
+    /// The original start of this instance, as generated by the recurrence set.
+    pub fn recurrence_id(&self) -> &When {
+        &self.recurrence_id
+    }
+
+    /// The start of this instance, after any override is applied.
+    pub fn start(&self) -> &When {
+        &self.start
+    }
+
+    /// The event whose properties apply to this instance: either an override or the master event.
+    pub fn event(&self) -> &'e VEvent {
+        self.event
+    }
+
+    pub fn is_override(&self) -> bool {
+        self.event.recurrence_id().is_some()
+    }
+
+    /// The RDATE period this instance was added by, if any; its end or duration applies to this instance
+    /// rather than the event's.
+    pub fn period(&self) -> Option<&'e Period> {
+        self.period
+    }
+}
+
+impl<'e> RecurrenceSet<'e> {
+    /// The recurrence set of a single event, without overrides.
+    pub fn new(master: &'e VEvent) -> Self {
+        RecurrenceSet { master, overrides: Vec::new(), zones: None }
+    }
+
+    /// The recurrence set of `master`, with overrides taken from those of `events` that have the same
+    /// UID and a RECURRENCE-ID. Where several override the same instance, the highest SEQUENCE wins.
+    pub fn with_overrides<I: IntoIterator<Item=&'e VEvent>>(master: &'e VEvent, events: I) -> Self {
+        let overrides = events.into_iter()
+            .filter(|e| e.uid() == master.uid() && e.recurrence_id().is_some())
+            .collect();
+        RecurrenceSet { master, overrides, zones: None }
+    }
+
+    /// Resolve TZIDs in the calendar's timezones when expanding rules (see `Rrule::occurrences_in`), and
+    /// when matching EXDATEs and RECURRENCE-IDs to instances in a different timezone.
+    pub fn in_timezones(mut self, zones: &'e Timezones) -> Self {
+        self.zones = Some(zones);
+        self
+    }
+
+    /// Group events by UID into recurrence sets. Each set's master is the event without a
+    /// RECURRENCE-ID; overrides with no master are not included.
+    pub fn from_events(events: &'e [VEvent]) -> Vec<RecurrenceSet<'e>> {
+        events.iter()
+            .filter(|e| e.recurrence_id().is_none())
+            .map(|master| RecurrenceSet::with_overrides(master, events))
+            .collect()
+    }
+
+    pub fn master(&self) -> &'e VEvent {
+        self.master
+    }
+
+    /// Iterate over the instances in order of RECURRENCE-ID (which, when overrides move instances,
+    /// need not be the order of their start times).
+    pub fn instances(&self) -> Instances<'_, 'e> {
+        let master = self.master;
+        let candidates: Box<dyn Iterator<Item=When> + 'e> = match (master.dtstart(), master.rrule()) {
+            (Some(dtstart), Some(rrule)) => Box::new(Occurrences::new(rrule, dtstart, self.zones)),
+            (Some(dtstart), None) => Box::new(std::iter::once(dtstart.clone())),
+            (None, _) => Box::new(std::iter::empty()),
+        };
+        let mut rdates: Vec<_> = match master.dtstart() {
+            Some(_) => master.rdates().iter().collect(),
+            None => Vec::new(),
+        };
+        rdates.sort_by_key(|r| r.start().naive());
+        let exrules = match master.dtstart() {
+            Some(dtstart) => master.exrules().iter().map(|r| Occurrences::new(r, dtstart, self.zones).unseeded().peekable()).collect(),
+            None => Vec::new(),
+        };
+        Instances {
+            set: self,
+            candidates: candidates.peekable(),
+            rdates: rdates.into_iter().peekable(),
+            exrules,
+            last: None,
+        }
+    }
+
+    /// Instances whose (overridden) start is at or after `start` and before `end`.
+    pub fn between<'s>(&'s self, start: &When, end: &When) -> impl Iterator<Item=Instance<'e>> + 's {
+        let (start, end) = (start.naive(), end.naive());
+        let max_shift = self.overrides.iter()
+            .filter_map(|o| Some(self.difference(o.dtstart()?, o.recurrence_id()?).abs()))
+            .max()
+            .unwrap_or_else(Duration::zero);
+        let limit = end.checked_add_signed(max_shift).unwrap_or(NaiveDateTime::MAX);
+        self.instances()
+            .take_while(move |i| i.recurrence_id.naive() < limit)
+            .filter(move |i| start <= i.start.naive() && i.start.naive() < end)
+    }
+
+    /// How much later `a` is than `b`. Values of the same kind (both floating, both UTC or in the same
+    /// TZID) are compared by wall-clock time; others by UTC time, where the timezones resolve both.
+    fn difference(&self, a: &When, b: &When) -> Duration {
+        if let (When::DateTime(x), When::DateTime(y), Some(zones)) = (a, b, self.zones) {
+            if !same_kind(x, y) {
+                if let (Some(x), Some(y)) = (zones.to_utc(x), zones.to_utc(y)) {
+                    return x - y;
+                }
+            }
+        }
+        a.naive() - b.naive()
+    }
+
+    fn excluded(&self, candidate: &When, exrules: &mut [Peekable<Occurrences<'e>>]) -> bool {
+        let t = candidate.naive();
+        let exdate = self.master.exdates().iter().any(|ex| match ex {
+            When::Date(d) => *d == t.date(),
+            When::DateTime(_) => self.difference(ex, candidate).is_zero(),
+        });
+        if exdate {
+            return true;
+        }
+        let mut excluded = false;
+        for exrule in exrules {
+            while exrule.peek().is_some_and(|ex| ex.naive() < t) {
+                exrule.next();
+            }
+            if exrule.peek().is_some_and(|ex| ex.naive() == t) {
+                excluded = true;
+            }
+        }
+        excluded
+    }
+
+    fn instance(&self, recurrence_id: When) -> Instance<'e> {
+        // How far this instance is after each override's RECURRENCE-ID.
+        let after = |o: &VEvent| o.recurrence_id().map(|rid| self.difference(&recurrence_id, rid));
+        let exact = self.overrides.iter()
+            .filter(|o| after(o).is_some_and(|d| d.is_zero()))
+            .max_by_key(|o| o.sequence());
+        let ranged = || self.overrides.iter()
+            .filter(|o| o.recurrence_range() == Some(&Range::Iana(IanaRange::ThisAndFuture)))
+            .filter_map(|o| Some((after(o).filter(|d| *d >= Duration::zero())?, o)))
+            .min_by_key(|&(d, o)| (d, std::cmp::Reverse(o.sequence())))
+            .map(|(_, o)| o);
+        match exact.or_else(ranged) {
+            Some(o) => {
+                // The override's DTSTART, moved as far as this instance is from its RECURRENCE-ID.
+                let start = match (after(o), o.dtstart()) {
+                    (Some(d), Some(dtstart)) => match dtstart.naive().checked_add_signed(d) {
+                        Some(t) => dtstart.with_naive(t),
+                        None => recurrence_id.clone(),
+                    },
+                    _ => recurrence_id.clone(),
+                };
+                Instance { recurrence_id, start, event: o, period: None }
+            }
+            None => Instance { start: recurrence_id.clone(), recurrence_id, event: self.master, period: None },
+        }
+    }
+}
+
+fn same_kind(a: &DateTime, b: &DateTime) -> bool {
+    match (a, b) {
+        (DateTime::Utc(_), DateTime::Utc(_)) | (DateTime::Floating(_), DateTime::Floating(_)) => true,
+        (DateTime::Local(_, a), DateTime::Local(_, b)) => a == b,
+        _ => false,
+    }
+}
+
+/// Iterator over the instances of a `RecurrenceSet`.
+pub struct Instances<'s, 'e> {
+    set: &'s RecurrenceSet<'e>,
+    candidates: Peekable<Box<dyn Iterator<Item=When> + 'e>>,
+    rdates: Peekable<std::vec::IntoIter<&'e RdateValue>>,
+    exrules: Vec<Peekable<Occurrences<'e>>>,
+    last: Option<NaiveDateTime>,
+}
+
+impl<'s, 'e> Iterator for Instances<'s, 'e> {
+    type Item = Instance<'e>;
+
+    fn next(&mut self) -> Option<Instance<'e>> {
+        loop {
+            let from_rule = match (self.candidates.peek(), self.rdates.peek()) {
+                (None, None) => return None,
+                (Some(_), None) => true,
+                (None, Some(_)) => false,
+                // An RDATE goes first, so that its period is kept if the rule generates the same instance.
+                (Some(c), Some(r)) => c.naive() < r.start().naive(),
+            };
+            let (candidate, period) = if from_rule {
+                (self.candidates.next()?, None)
+            } else {
+                let rdate = self.rdates.next()?;
+                (rdate.start(), rdate.period())
+            };
+            let t = candidate.naive();
+            if self.last == Some(t) {
+                continue;
+            }
+            self.last = Some(t);
+            if self.set.excluded(&candidate, &mut self.exrules) {
+                continue;
+            }
+            let mut instance = self.set.instance(candidate);
+            if !instance.is_override() {
+                instance.period = period;
+            }
+            return Some(instance);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::component::Component;
+    use crate::Lexer;
+
+    fn event(properties: &str) -> VEvent {
+        let input = format!("BEGIN:VEVENT\r\nUID:abc\r\nDTSTAMP:20240101T120000Z\r\n{}END:VEVENT\r\n", properties);
+        VEvent::from_component(&Component::parse(&mut Lexer::new(input.as_bytes())).unwrap().unwrap()).unwrap()
+    }
+
+    fn format(times: impl Iterator<Item=When>) -> Vec<String> {
+        times.map(|w| w.naive().format("%Y%m%dT%H%M%S").to_string()).collect()
+    }
+
+    /// The first `n` occurrences of `rrule` from a floating DTSTART.
+    fn expand(dtstart: &str, rrule: &str, n: usize) -> Vec<String> {
+        let ev = event(&format!("DTSTART:{}\r\nRRULE:{}\r\n", dtstart, rrule));
+        format(ev.rrule().unwrap().occurrences(ev.dtstart().unwrap()).take(n))
+    }
+
+    fn days(dates: &[&str]) -> Vec<String> {
+        dates.iter().map(|d| format!("{}T090000", d)).collect()
+    }
+
+    // The examples of RFC 5545 section 3.8.5.3, with floating rather than America/New_York times.
+    #[test]
+    fn daily() {
+        assert_eq!(expand("19970902T090000", "FREQ=DAILY;COUNT=10", 100), days(&[
+            "19970902", "19970903", "19970904", "19970905", "19970906",
+            "19970907", "19970908", "19970909", "19970910", "19970911",
+        ]));
+        assert_eq!(expand("19970902T090000", "FREQ=DAILY;INTERVAL=10;COUNT=5", 100), days(&[
+            "19970902", "19970912", "19970922", "19971002", "19971012",
+        ]));
+        let every_other = expand("19970902T090000", "FREQ=DAILY;INTERVAL=2", 1000);
+        assert_eq!(every_other[..3], days(&["19970902", "19970904", "19970906"])[..]);
+        assert_eq!(every_other.len(), 1000);
+        assert_eq!(expand("19980101T090000", "FREQ=DAILY;UNTIL=20000131T140000Z;BYMONTH=1", 1000).len(), 93);
+    }
+
+    #[test]
+    fn weekly() {
+        assert_eq!(expand("19970902T090000", "FREQ=WEEKLY;COUNT=4", 100), days(&[
+            "19970902", "19970909", "19970916", "19970923",
+        ]));
+        assert_eq!(expand("19970902T090000", "FREQ=WEEKLY;UNTIL=19971007T000000Z;WKST=SU;BYDAY=TU,TH", 100), days(&[
+            "19970902", "19970904", "19970909", "19970911", "19970916",
+            "19970918", "19970923", "19970925", "19970930", "19971002",
+        ]));
+        let mwf = expand("19970901T090000", "FREQ=WEEKLY;INTERVAL=2;UNTIL=19971224T000000Z;WKST=SU;BYDAY=MO,WE,FR", 100);
+        assert_eq!(mwf.len(), 25);
+        assert_eq!(mwf[..4], days(&["19970901", "19970903", "19970905", "19970915"])[..]);
+        assert_eq!(mwf[24], "19971222T090000");
+    }
+
+    #[test]
+    fn week_start() {
+        assert_eq!(expand("19970805T090000", "FREQ=WEEKLY;INTERVAL=2;COUNT=4;BYDAY=TU,SU;WKST=MO", 100), days(&[
+            "19970805", "19970810", "19970819", "19970824",
+        ]));
+        assert_eq!(expand("19970805T090000", "FREQ=WEEKLY;INTERVAL=2;COUNT=4;BYDAY=TU,SU;WKST=SU", 100), days(&[
+            "19970805", "19970817", "19970819", "19970831",
+        ]));
+    }
+
+    #[test]
+    fn monthly() {
+        assert_eq!(expand("19970905T090000", "FREQ=MONTHLY;COUNT=10;BYDAY=1FR", 100), days(&[
+            "19970905", "19971003", "19971107", "19971205", "19980102",
+            "19980206", "19980306", "19980403", "19980501", "19980605",
+        ]));
+        assert_eq!(expand("19970922T090000", "FREQ=MONTHLY;COUNT=6;BYDAY=-2MO", 100), days(&[
+            "19970922", "19971020", "19971117", "19971222", "19980119", "19980216",
+        ]));
+        assert_eq!(expand("19970928T090000", "FREQ=MONTHLY;BYMONTHDAY=-3", 6), days(&[
+            "19970928", "19971029", "19971128", "19971229", "19980129", "19980226",
+        ]));
+        assert_eq!(expand("19970902T090000", "FREQ=MONTHLY;COUNT=10;BYMONTHDAY=2,15", 100), days(&[
+            "19970902", "19970915", "19971002", "19971015", "19971102",
+            "19971115", "19971202", "19971215", "19980102", "19980115",
+        ]));
+        // February 30 is skipped, not moved.
+        assert_eq!(expand("20070115T090000", "FREQ=MONTHLY;BYMONTHDAY=15,30;COUNT=5", 100), days(&[
+            "20070115", "20070130", "20070215", "20070315", "20070330",
+        ]));
+        assert_eq!(expand("19970902T090000", "FREQ=MONTHLY;BYMONTHDAY=13;BYDAY=FR", 5), days(&[
+            "19970902", "19980213", "19980313", "19981113", "19990813",
+        ]));
+        assert_eq!(expand("19970929T090000", "FREQ=MONTHLY;BYDAY=MO,TU,WE,TH,FR;BYSETPOS=-2", 7), days(&[
+            "19970929", "19971030", "19971127", "19971230", "19980129", "19980226", "19980330",
+        ]));
+    }
+
+    #[test]
+    fn yearly() {
+        assert_eq!(expand("19970610T090000", "FREQ=YEARLY;COUNT=10;BYMONTH=6,7", 100), days(&[
+            "19970610", "19970710", "19980610", "19980710", "19990610",
+            "19990710", "20000610", "20000710", "20010610", "20010710",
+        ]));
+        assert_eq!(expand("19970519T090000", "FREQ=YEARLY;BYDAY=20MO", 3), days(&[
+            "19970519", "19980518", "19990517",
+        ]));
+        assert_eq!(expand("19970512T090000", "FREQ=YEARLY;BYWEEKNO=20;BYDAY=MO", 3), days(&[
+            "19970512", "19980511", "19990517",
+        ]));
+        assert_eq!(expand("19970101T090000", "FREQ=YEARLY;INTERVAL=3;COUNT=10;BYYEARDAY=1,100,200", 100), days(&[
+            "19970101", "19970410", "19970719", "20000101", "20000409",
+            "20000718", "20030101", "20030410", "20030719", "20060101",
+        ]));
+    }
+
+    #[test]
+    fn subdaily() {
+        assert_eq!(expand("19970902T090000", "FREQ=MINUTELY;INTERVAL=15;COUNT=6", 100), [
+            "19970902T090000", "19970902T091500", "19970902T093000",
+            "19970902T094500", "19970902T100000", "19970902T101500",
+        ]);
+        assert_eq!(expand("19970902T090000", "FREQ=MINUTELY;INTERVAL=20;BYHOUR=9,10,11,12,13,14,16", 10), [
+            "19970902T090000", "19970902T092000", "19970902T094000", "19970902T100000", "19970902T102000",
+            "19970902T104000", "19970902T110000", "19970902T112000", "19970902T114000", "19970902T120000",
+        ]);
+    }
+
+    #[test]
+    fn large_interval_ends_iteration() {
+        for rule in &["FREQ=DAILY;INTERVAL=4000000000", "FREQ=WEEKLY;INTERVAL=4000000000"] {
+            assert_eq!(expand("19970902T090000", rule, 10), ["19970902T090000"], "{}", rule);
+        }
+        assert_eq!(expand("19970902T090000", "FREQ=HOURLY;INTERVAL=4000000000;BYMINUTE=1", 10),
+                   ["19970902T090000", "19970902T090100"]);
+        // Every 126 years, until the year 9999.
+        assert_eq!(expand("19970902T090000", "FREQ=SECONDLY;INTERVAL=4000000000", usize::MAX).len(), 64);
+    }
+
+    #[test]
+    fn out_of_range_parts() {
+        for rule in &["FREQ=MINUTELY;BYSECOND=60", "FREQ=DAILY;BYHOUR=24", "FREQ=YEARLY;BYMONTH=0",
+                      "FREQ=MONTHLY;BYMONTHDAY=-32", "FREQ=YEARLY;BYWEEKNO=54", "FREQ=MONTHLY;BYDAY=0MO"] {
+            let input = format!("BEGIN:VEVENT\r\nUID:abc\r\nDTSTAMP:20240101T120000Z\r\nRRULE:{}\r\nEND:VEVENT\r\n", rule);
+            let component = Component::parse(&mut Lexer::new(input.as_bytes())).unwrap().unwrap();
+            assert!(VEvent::from_component(&component).is_err(), "{}", rule);
+        }
+    }
+
+    #[test]
+    fn utc_until() {
+        let calendar = "BEGIN:VCALENDAR\r\nBEGIN:VTIMEZONE\r\nTZID:East\r\nBEGIN:STANDARD\r\n\
+                        DTSTART:19700101T000000\r\nTZOFFSETFROM:+0500\r\nTZOFFSETTO:+0500\r\nEND:STANDARD\r\n\
+                        END:VTIMEZONE\r\nBEGIN:VTIMEZONE\r\nTZID:West\r\nBEGIN:STANDARD\r\n\
+                        DTSTART:19700101T000000\r\nTZOFFSETFROM:-0500\r\nTZOFFSETTO:-0500\r\nEND:STANDARD\r\n\
+                        END:VTIMEZONE\r\nEND:VCALENDAR\r\n";
+        let calendar = Component::parse(&mut Lexer::new(calendar.as_bytes())).unwrap().unwrap();
+        let zones = Timezones::from_calendar(&calendar).unwrap();
+        // 09:00 on the 3rd is 04:00 UTC in East and 14:00 UTC in West.
+        let until = "FREQ=DAILY;UNTIL=20240103T100000Z";
+        let east = event(&format!("DTSTART;TZID=East:20240101T090000\r\nRRULE:{}\r\n", until));
+        assert_eq!(format(east.rrule().unwrap().occurrences_in(east.dtstart().unwrap(), &zones)).len(), 3);
+        let west = event(&format!("DTSTART;TZID=West:20240101T090000\r\nRRULE:{}\r\n", until));
+        assert_eq!(format(west.rrule().unwrap().occurrences_in(west.dtstart().unwrap(), &zones)).len(), 2);
+        // A UTC DTSTART is compared directly.
+        assert_eq!(expand("20240101T090000Z", until, 10).len(), 3);
+    }
+
+    fn events(text: &str) -> Vec<VEvent> {
+        let input = text.replace('\n', "\r\n");
+        let calendar = Component::parse(&mut Lexer::new(input.as_bytes())).unwrap().unwrap();
+        calendar.children().map(|c| VEvent::from_component(c).unwrap()).collect()
+    }
+
+    fn starts<'e>(instances: impl Iterator<Item=Instance<'e>>) -> Vec<String> {
+        format(instances.map(|i| i.start().clone()))
+    }
+
+    #[test]
+    fn rdates_and_exdates() {
+        let events = events("BEGIN:VCALENDAR
+BEGIN:VEVENT
+UID:abc
+DTSTAMP:20240101T120000Z
+DTSTART:20240101T090000
+RRULE:FREQ=DAILY;COUNT=4
+RDATE:20240102T090000,20240102T120000
+RDATE;VALUE=PERIOD:20231231T090000/PT1H
+EXDATE:20240103T090000
+END:VEVENT
+END:VCALENDAR
+");
+        let set = RecurrenceSet::new(&events[0]);
+        assert_eq!(starts(set.instances()), [
+            "20231231T090000", "20240101T090000", "20240102T090000", "20240102T120000", "20240104T090000",
+        ]);
+        assert!(set.instances().next().unwrap().period().is_some());
+        assert!(set.instances().skip(1).all(|i| i.period().is_none() && !i.is_override()));
+        let between = set.between(&When::Date(NaiveDate::from_ymd_opt(2024, 1, 2).unwrap()),
+                                  &When::Date(NaiveDate::from_ymd_opt(2024, 1, 4).unwrap()));
+        assert_eq!(starts(between), ["20240102T090000", "20240102T120000"]);
+    }
+
+    #[test]
+    fn exrule() {
+        let events = events("BEGIN:VCALENDAR
+BEGIN:VEVENT
+UID:abc
+DTSTAMP:20240101T120000Z
+DTSTART:20240101T090000
+RRULE:FREQ=DAILY;COUNT=10
+EXRULE:FREQ=WEEKLY;BYDAY=WE,FR;COUNT=3
+END:VEVENT
+BEGIN:VEVENT
+UID:def
+DTSTAMP:20240101T120000Z
+DTSTART:20240101T090000
+RRULE:FREQ=DAILY;COUNT=3
+EXRULE:FREQ=DAILY;INTERVAL=2
+END:VEVENT
+END:VCALENDAR
+");
+        // DTSTART (a Monday) is not excluded when the EXRULE does not generate it.
+        assert_eq!(starts(RecurrenceSet::new(&events[0]).instances()), days(&[
+            "20240101", "20240102", "20240104", "20240106", "20240107", "20240108", "20240109",
+        ]));
+        // ... and is when it does.
+        assert_eq!(starts(RecurrenceSet::new(&events[1]).instances()), days(&["20240102"]));
+    }
+
+    #[test]
+    fn overrides() {
+        let events = events("BEGIN:VCALENDAR
+BEGIN:VEVENT
+UID:abc
+DTSTAMP:20240101T120000Z
+DTSTART:20240101T090000
+RRULE:FREQ=DAILY;COUNT=5
+END:VEVENT
+BEGIN:VEVENT
+UID:abc
+DTSTAMP:20240101T120000Z
+RECURRENCE-ID:20240102T090000
+DTSTART:20240102T100000
+SEQUENCE:1
+END:VEVENT
+BEGIN:VEVENT
+UID:abc
+DTSTAMP:20240101T120000Z
+RECURRENCE-ID:20240102T090000
+DTSTART:20240102T110000
+SEQUENCE:2
+END:VEVENT
+BEGIN:VEVENT
+UID:abc
+DTSTAMP:20240101T120000Z
+RECURRENCE-ID;RANGE=THISANDFUTURE:20240104T090000
+DTSTART:20240104T080000
+END:VEVENT
+BEGIN:VEVENT
+UID:other
+DTSTAMP:20240101T120000Z
+RECURRENCE-ID:20240103T090000
+DTSTART:20240103T100000
+END:VEVENT
+END:VCALENDAR
+");
+        let sets = RecurrenceSet::from_events(&events);
+        assert_eq!(sets.len(), 1);
+        let instances: Vec<_> = sets[0].instances().collect();
+        assert_eq!(format(instances.iter().map(|i| i.recurrence_id().clone())), days(&[
+            "20240101", "20240102", "20240103", "20240104", "20240105",
+        ]));
+        // The highest SEQUENCE wins, and THISANDFUTURE moves later instances too.
+        assert_eq!(format(instances.iter().map(|i| i.start().clone())), [
+            "20240101T090000", "20240102T110000", "20240103T090000", "20240104T080000", "20240105T080000",
+        ]);
+        assert_eq!(instances[1].event().sequence(), 2);
+        assert_eq!(instances.iter().filter(|i| i.is_override()).count(), 3);
+    }
+
+    #[test]
+    fn utc_exceptions() {
+        let input = "BEGIN:VCALENDAR
+BEGIN:VTIMEZONE
+TZID:X
+BEGIN:STANDARD
+DTSTART:19700101T000000
+TZOFFSETFROM:-0500
+TZOFFSETTO:-0500
+END:STANDARD
+END:VTIMEZONE
+BEGIN:VEVENT
+UID:abc
+DTSTAMP:20240101T120000Z
+DTSTART;TZID=X:20240101T100000
+RRULE:FREQ=DAILY;COUNT=4
+EXDATE:20240102T150000Z
+END:VEVENT
+BEGIN:VEVENT
+UID:abc
+DTSTAMP:20240101T120000Z
+RECURRENCE-ID:20240103T150000Z
+DTSTART;TZID=X:20240103T120000
+END:VEVENT
+BEGIN:VEVENT
+UID:abc
+DTSTAMP:20240101T120000Z
+RECURRENCE-ID;TZID=X:20240104T150000
+DTSTART;TZID=X:20240104T160000
+END:VEVENT
+END:VCALENDAR
+".replace('\n', "\r\n");
+        let calendar = Component::parse(&mut Lexer::new(input.as_bytes())).unwrap().unwrap();
+        let zones = Timezones::from_calendar(&calendar).unwrap();
+        let events: Vec<_> = calendar.children()
+            .filter(|c| c.is(crate::component::IanaComponent::Vevent))
+            .map(|c| VEvent::from_component(c).unwrap())
+            .collect();
+        // 10:00 in X is 15:00 UTC, so the EXDATE and the first RECURRENCE-ID apply; the second, at 15:00
+        // in X, matches no instance.
+        let set = RecurrenceSet::with_overrides(&events[0], &events).in_timezones(&zones);
+        assert_eq!(starts(set.instances()), ["20240101T100000", "20240103T120000", "20240104T100000"]);
+        assert!(set.instances().nth(1).unwrap().is_override());
+        // Without the timezones, they are compared by wall-clock time.
+        let set = RecurrenceSet::with_overrides(&events[0], &events);
+        assert_eq!(set.instances().count(), 4);
+
+        // Overrides do not move the end of the search past the largest time.
+        let max = When::DateTime(DateTime::Floating(NaiveDateTime::MAX));
+        let set = RecurrenceSet::with_overrides(&events[0], &events).in_timezones(&zones);
+        assert_eq!(set.between(events[0].dtstart().unwrap(), &max).count(), 3);
+    }
+}
@@ -0,0 +1,565 @@
+//! A lexer over input that is entirely in memory. Content lines borrow from the input: parameter values
+//! and values are copied only when a line is folded, or contains escapes.
+
+use crate::{blank_line, check_single_value, decode_carets, latin1, unescape_text, value_type};
+use crate::{Bad, IanaParam, ParamName, ParserOptions, Position, Property, Result, Span, ValueType};
+use crate::{Warning, WarningCode};
+use std::borrow::Cow;
+use std::ops::Range;
+
+#[derive(Clone, Debug)]
+pub struct Param<'a> {
+    name: ParamName,
+    values: Vec<Cow<'a, str>>,
+    span: Span,
+}
+impl<'a> Param<'a> {
+    pub fn name(&self) -> &ParamName {
+        &self.name
+    }
+
+    pub fn values(&self) -> impl Iterator<Item=&str> {
+        self.values.iter().map(|s| s.as_ref())
+    }
+
+    pub fn span(&self) -> Span {
+        self.span
+    }
+
+    pub fn into_owned(self) -> crate::Param {
+        let values = self.values.into_iter().map(Cow::into_owned).collect();
+        crate::Param { name: self.name, values, span: self.span }
+    }
+}
+
+/// A content line borrowing from the input; see `crate::ContentLine`.
+#[derive(Clone, Debug)]
+pub struct ContentLine<'a> {
+    name: Property,
+    params: Vec<Param<'a>>,
+    value: Cow<'a, str>,
+    raw_value: Cow<'a, str>,
+    span: Span,
+    name_span: Span,
+    value_span: Span,
+    raw: Option<&'a [u8]>,
+}
+impl<'a> ContentLine<'a> {
+    pub fn name(&self) -> &Property {
+        &self.name
+    }
+
+    pub fn params(&self) -> impl Iterator<Item=&Param<'a>> {
+        self.params.iter()
+    }
+
+    pub fn values_of(&self, pn: IanaParam) -> Option<impl Iterator<Item=&str>> {
+        let param = self.params.iter().find(|p| p.name == ParamName::Iana(pn))?;
+        Some(param.values())
+    }
+
+    /// The first value of the parameter.
+    pub fn value_of(&self, pn: IanaParam) -> Option<&str> {
+        self.values_of(pn)?.next()
+    }
+
+    /// The value. A TEXT value is unescaped; values of other types are as they appeared in the input.
+    pub fn value(&self) -> &str {
+        &self.value
+    }
+
+    /// The value as it appeared in the input, with any escapes.
+    pub fn raw_value(&self) -> &str {
+        &self.raw_value
+    }
+
+    pub fn into_value(self) -> Cow<'a, str> {
+        self.value
+    }
+
+    /// The type of the value: given by the VALUE parameter if present, otherwise the property's default.
+    pub fn value_type(&self) -> ValueType {
+        value_type(&self.name, self.value_of(IanaParam::Value))
+    }
+
+    pub fn line(&self) -> usize {
+        self.span.start.line
+    }
+
+    pub fn position(&self) -> Position {
+        self.span.start
+    }
+
+    /// Where the line was in the input, not including the line break.
+    pub fn span(&self) -> Span {
+        self.span
+    }
+
+    pub fn name_span(&self) -> Span {
+        self.name_span
+    }
+
+    pub fn value_span(&self) -> Span {
+        self.value_span
+    }
+
+    /// The line exactly as it appeared in the input, including any folding, the line break and blank
+    /// lines around it (see `ParserOptions::retain_raw`), if the lexer was retaining raw text.
+    pub fn raw(&self) -> Option<&'a [u8]> {
+        self.raw
+    }
+
+    pub fn into_owned(self) -> crate::ContentLine {
+        let raw_value = match &self.value {
+            Cow::Owned(value) if *value != self.raw_value => Some(self.raw_value.into_owned()),
+            _ => None,
+        };
+        crate::ContentLine {
+            name: self.name,
+            num_params: self.params.len(),
+            params: self.params.into_iter().map(Param::into_owned).collect(),
+            value: self.value.into_owned(),
+            raw_value,
+            value_set: false,
+            span: self.span,
+            name_span: self.name_span,
+            value_span: self.value_span,
+            raw: self.raw.map(<[u8]>::to_vec),
+        }
+    }
+}
+
+/// The physical lines of a content line, joined with the folds removed.
+struct Unfolded<'a> {
+    text: Cow<'a, [u8]>,
+    start: Position,
+    /// For each continuation line, where its content begins in `text` and in the input.
+    folds: Vec<(usize, Position)>,
+    /// Line breaks that are not CRLF.
+    bare_breaks: Vec<Position>,
+    raw: &'a [u8],
+}
+impl<'a> Unfolded<'a> {
+    /// The input position of an index into `text`. An index where a fold is is after the fold.
+    fn position(&self, i: usize) -> Position {
+        self.position_from(i, self.folds.iter().rev().find(|(at, _)| *at <= i))
+    }
+
+    /// A span begins before any fold at its start, and ends after any fold at its end, as in `crate::Lexer`.
+    fn span(&self, range: Range<usize>) -> Span {
+        let start = self.position_from(range.start, self.folds.iter().rev().find(|(at, _)| *at < range.start));
+        Span::new(start, self.position(range.end))
+    }
+
+    fn position_from(&self, i: usize, fold: Option<&(usize, Position)>) -> Position {
+        let (at, base) = fold.copied().unwrap_or((0, self.start));
+        let d = i - at;
+        Position { line: base.line, column: base.column + d, offset: base.offset + d }
+    }
+
+    fn sub(&self, range: Range<usize>) -> Cow<'a, [u8]> {
+        match &self.text {
+            Cow::Borrowed(text) => Cow::Borrowed(&text[range]),
+            Cow::Owned(text) => Cow::Owned(text[range].to_vec()),
+        }
+    }
+}
+
+fn is_name_char(c: u8) -> bool {
+    c == b'-' || c.is_ascii_alphanumeric()
+}
+
+/// The end of a parameter value starting at `i`: the first of `ends`.
+fn scan(text: &[u8], i: usize, ends: &[u8]) -> usize {
+    text[i..].iter().position(|c| ends.contains(c)).map_or(text.len(), |n| i + n)
+}
+
+pub struct Lexer<'a> {
+    input: &'a [u8],
+    options: ParserOptions,
+    /// Offset of the input within a larger stream.
+    base: usize,
+    offset: usize,
+    line: usize,
+    /// Offset of the start of the current physical line.
+    line_start: usize,
+    warnings: Vec<Warning>,
+}
+
+impl<'a> Lexer<'a> {
+    /// A lexer with `ParserOptions::default()`, as `crate::Lexer::new`.
+    pub fn new(input: &'a [u8]) -> Self {
+        Self::with_options(input, ParserOptions::default())
+    }
+
+    pub fn with_options(input: &'a [u8], options: ParserOptions) -> Self {
+        Self::starting_at(input, options, 1, 0)
+    }
+
+    /// Lex a part of a larger stream, beginning at the start of physical line `line`, `offset` octets in.
+    pub(crate) fn starting_at(input: &'a [u8], options: ParserOptions, line: usize, offset: usize) -> Self {
+        Lexer { input, options, base: offset, offset: 0, line, line_start: 0, warnings: Vec::new() }
+    }
+
+    pub fn options(&self) -> &ParserOptions {
+        &self.options
+    }
+
+    /// Current position in the input.
+    pub fn position(&self) -> Position {
+        Position { line: self.line, column: self.offset - self.line_start + 1, offset: self.base + self.offset }
+    }
+
+    /// Warnings about the input read so far. They accumulate until taken with `take_warnings`.
+    pub fn warnings(&self) -> &[Warning] {
+        &self.warnings
+    }
+
+    pub fn take_warnings(&mut self) -> Vec<Warning> {
+        std::mem::take(&mut self.warnings)
+    }
+
+    /// Iterate over the remaining content lines.
+    pub fn content_lines(&mut self) -> ContentLines<'_, 'a> {
+        ContentLines { lexer: self }
+    }
+
+    fn warn(&mut self, code: WarningCode, span: Span, message: String) {
+        self.warnings.push(Warning::new(code, span, message));
+    }
+
+    /// Read the next content line. Blank lines are skipped. After a recoverable error (see
+    /// `Error::is_recoverable`), lexing can continue with the next line.
+    pub fn lex_content_line(&mut self) -> Result<Option<ContentLine<'a>>> {
+        let begin = self.offset;
+        loop {
+            if self.offset >= self.input.len() {
+                return Ok(None);
+            }
+            let line = self.unfold();
+            let warned = self.warnings.len();
+            let mut bad_break = None;
+            for &at in &line.bare_breaks {
+                if !self.options.bare_line_breaks {
+                    bad_break = Some(at);
+                    break;
+                }
+                self.warn(WarningCode::BareLineBreak, Span::at(at), "line break is not CRLF".to_owned());
+            }
+            if line.text.is_empty() {
+                match bad_break {
+                    Some(at) => return Err(Bad::LineBreak.at(Span::at(at))),
+                    None => continue,
+                }
+            }
+            // Report warnings, and the first error, in the order that the buffered lexer comes to them: a bad
+            // name or value is found once it has been read, and an unknown property at the end of the line.
+            let coli = self.parse(&line);
+            self.warnings[warned..].sort_by_key(|w| w.span().end.offset);
+            if let Some(at) = bad_break {
+                let first = matches!(&coli, Err(e) if !matches!(e.condition, Bad::Property { .. }) && e.span().end.offset < at.offset);
+                if !first {
+                    let warnings = self.warnings.split_off(warned);
+                    self.warnings.extend(warnings.into_iter().filter(|w| w.span().end.offset < at.offset));
+                    return Err(Bad::LineBreak.at(Span::at(at)));
+                }
+            }
+            let mut coli = coli?;
+            if self.options.retain_raw {
+                self.skip_blank_lines();
+                coli.raw = Some(&self.input[begin..self.offset]);
+            }
+            return Ok(Some(coli));
+        }
+    }
+
+    /// Consume the blank lines that follow the current content line.
+    fn skip_blank_lines(&mut self) {
+        while let Some(n) = blank_line(&self.input[self.offset..], self.options.bare_line_breaks) {
+            if n == 1 {
+                let at = self.position();
+                self.warn(WarningCode::BareLineBreak, Span::at(at), "line break is not CRLF".to_owned());
+            }
+            self.offset += n;
+            self.line += 1;
+            self.line_start = self.offset;
+        }
+    }
+
+    /// Read the physical lines of the next content line.
+    fn unfold(&mut self) -> Unfolded<'a> {
+        let start = self.position();
+        let begin = self.offset;
+        let mut text = Cow::Borrowed(&[][..]);
+        let mut folds = Vec::new();
+        let mut bare_breaks = Vec::new();
+        loop {
+            let rest = &self.input[self.offset..];
+            let end = rest.iter().position(|&c| c == b'\r' || c == b'\n').unwrap_or(rest.len());
+            if folds.is_empty() {
+                text = Cow::Borrowed(&rest[..end]);
+            } else {
+                text.to_mut().extend_from_slice(&rest[..end]);
+            }
+            let line_break = match rest[end..] {
+                [b'\r', b'\n', ..] => 2,
+                [] => 0,
+                _ => 1,
+            };
+            self.offset += end;
+            if line_break == 1 {
+                bare_breaks.push(self.position());
+            }
+            if line_break == 0 {
+                break;
+            }
+            self.offset += line_break;
+            self.line += 1;
+            self.line_start = self.offset;
+            match self.input.get(self.offset) {
+                Some(b' ') | Some(b'\t') => {
+                    self.offset += 1;
+                    folds.push((text.len(), self.position()));
+                }
+                _ => break,
+            }
+        }
+        Unfolded { text, start, folds, bare_breaks, raw: &self.input[begin..self.offset] }
+    }
+
+    /// Convert a lowercase name to uppercase, if allowed.
+    fn normalize_case<'t>(&mut self, name: &'t [u8], span: Span) -> Cow<'t, [u8]> {
+        if !self.options.lowercase_names || !name.iter().any(u8::is_ascii_lowercase) {
+            return Cow::Borrowed(name);
+        }
+        self.warn(WarningCode::LowercaseName, span, format!("lowercase name {}", String::from_utf8_lossy(name)));
+        Cow::Owned(name.to_ascii_uppercase())
+    }
+
+    fn decode(&mut self, s: Cow<'a, [u8]>, span: Span, what: &str) -> std::result::Result<Cow<'a, str>, Bad> {
+        let (bytes, error) = match s {
+            Cow::Borrowed(s) => match std::str::from_utf8(s) {
+                Ok(s) => return Ok(Cow::Borrowed(s)),
+                Err(e) => (s.to_vec(), e),
+            },
+            Cow::Owned(s) => match String::from_utf8(s) {
+                Ok(s) => return Ok(Cow::Owned(s)),
+                Err(e) => (e.as_bytes().to_vec(), e.utf8_error()),
+            },
+        };
+        if !self.options.latin1_fallback {
+            return Err(Bad::Encoding(error));
+        }
+        self.warn(WarningCode::Latin1, span, format!("{} is not UTF-8; decoded as Latin-1", what));
+        Ok(Cow::Owned(latin1(&bytes)))
+    }
+
+    fn parse(&mut self, line: &Unfolded<'a>) -> Result<ContentLine<'a>> {
+        let text: &[u8] = &line.text;
+        let unexpected = |i: usize, expected| {
+            let found = text.get(i).copied().unwrap_or(b'\n');
+            Bad::Unexpected { found, expected }.at(Span::at(line.position(i)))
+        };
+        let mut i = text.iter().position(|&c| !is_name_char(c)).unwrap_or(text.len());
+        let name_span = line.span(0..i);
+        let ident = self.normalize_case(&text[..i], name_span);
+        let name = match Property::from_bytes(&ident) {
+            Ok(name) => Ok(name),
+            Err(token) if self.options.allows_unregistered_names() && !token.is_empty() => {
+                let token = String::from_utf8_lossy(token).into_owned();
+                self.warn(WarningCode::UnregisteredName, name_span, format!("unregistered property {}", token));
+                Ok(Property::Other(token))
+            }
+            Err(e) => Err(String::from_utf8_lossy(e).into_owned()),
+        };
+
+        let mut params = Vec::new();
+        loop {
+            match text.get(i) {
+                Some(b';') => i += 1,
+                Some(b':') => {
+                    i += 1;
+                    break;
+                }
+                _ => return Err(unexpected(i, "';' or ':' after name")),
+            }
+            let mark = i;
+            i += text[i..].iter().position(|&c| !is_name_char(c)).unwrap_or(text.len() - i);
+            let span = line.span(mark..i);
+            let ident = self.normalize_case(&text[mark..i], span);
+            let name = match ParamName::from_bytes(&ident) {
+                Ok(name) => name,
+                Err(token) if self.options.allows_unregistered_names() && !token.is_empty() => {
+                    let token = String::from_utf8_lossy(token).into_owned();
+                    self.warn(WarningCode::UnregisteredName, span, format!("unregistered parameter {}", token));
+                    ParamName::Other(token)
+                }
+                Err(e) => return Err(Bad::Param(e.to_owned()).at(span)),
+            };
+            if text.get(i) != Some(&b'=') {
+                return Err(unexpected(i, "'=' after parameter name"));
+            }
+            i += 1;
+            let mut values = Vec::new();
+            loop {
+                let value = if text.get(i) == Some(&b'"') {
+                    let end = scan(text, i + 1, b"\"");
+                    if end == text.len() {
+                        return Err(unexpected(end, "closing DQUOTE"));
+                    }
+                    let value = line.sub(i + 1..end);
+                    i = end + 1;
+                    value
+                } else {
+                    let end = scan(text, i, b",;:");
+                    let value = line.sub(i..end);
+                    i = end;
+                    value
+                };
+                let span = line.span(mark..i);
+                let value = match decode_carets(&value) {
+                    Some(decoded) => Cow::Owned(decoded),
+                    None => value,
+                };
+                values.push(self.decode(value, span, "parameter value").map_err(|bad| bad.at(span))?);
+                match text.get(i) {
+                    Some(b',') => i += 1,
+                    Some(b';') | Some(b':') => break,
+                    _ => return Err(unexpected(i, "',', ';' or ':' after parameter value")),
+                }
+            }
+            let span = line.span(mark..i);
+            self.warnings.extend(check_single_value(&name, values.len(), span));
+            params.push(Param { name, values, span });
+        }
+
+        let value_span = line.span(i..text.len());
+        let value = line.sub(i..text.len());
+        let name = match name {
+            Ok(name) => name,
+            Err(name) => {
+                let params = params.into_iter().map(Param::into_owned).collect();
+                let value = String::from_utf8_lossy(&value).into_owned();
+                return Err(Bad::Property { name, params, value }.at(name_span));
+            }
+        };
+        let raw_value = self.decode(value, value_span, "value").map_err(|bad| bad.at(value_span))?;
+        let value_param = params.iter().find(|p| p.name == ParamName::Iana(IanaParam::Value)).and_then(|p| p.values.first());
+        let value = match value_type(&name, value_param.map(|v| v.as_ref())) {
+            ValueType::Text => unescape_text(&raw_value).map_or_else(|| raw_value.clone(), Cow::Owned),
+            _ => raw_value.clone(),
+        };
+        Ok(ContentLine {
+            name,
+            params,
+            value,
+            raw_value,
+            span: line.span(0..text.len()),
+            name_span,
+            value_span,
+            raw: if self.options.retain_raw { Some(line.raw) } else { None },
+        })
+    }
+}
+
+/// Content lines, and errors; lexing continues after an error.
+pub struct ContentLines<'l, 'a> {
+    lexer: &'l mut Lexer<'a>,
+}
+impl<'a> Iterator for ContentLines<'_, 'a> {
+    type Item = Result<ContentLine<'a>>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        self.lexer.lex_content_line().transpose()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// What a content line or error consists of, for comparing the lexers.
+    type Lexed = std::result::Result<(String, Vec<(String, Vec<String>, Span)>, String, String, [Span; 3], Option<Vec<u8>>), (crate::ErrorKind, Span, String)>;
+
+    fn summarize(result: Result<crate::ContentLine>) -> Lexed {
+        result.map(|coli| {
+            let params = coli.params().map(|p| (p.name().as_str().to_owned(), p.values().map(str::to_owned).collect(), p.span())).collect();
+            (coli.name().as_str().to_owned(), params, coli.value().to_owned(), coli.raw_value().to_owned(),
+             [coli.span(), coli.name_span(), coli.value_span()], coli.raw().map(<[u8]>::to_vec))
+        }).map_err(|e| (e.kind(), e.span(), e.to_string()))
+    }
+
+    /// Lex `input` with both lexers, and check that they agree on every line, error and warning.
+    fn compare(input: &[u8], options: ParserOptions) {
+        let mut slice = Lexer::with_options(input, options);
+        let mut buffered = crate::Lexer::with_options(input, options);
+        let from_slice: Vec<_> = slice.content_lines().map(|r| summarize(r.map(ContentLine::into_owned))).collect();
+        let from_buffered: Vec<_> = buffered.content_lines().map(summarize).collect();
+        assert_eq!(from_slice, from_buffered, "{:?}", String::from_utf8_lossy(input));
+        assert_eq!(slice.warnings(), buffered.warnings(), "{:?}", String::from_utf8_lossy(input));
+    }
+
+    const INPUTS: &[&[u8]] = &[
+        b"BEGIN:VEVENT\r\nUID:1\r\nEND:VEVENT\r\n",
+        b"SUMMARY;LANGUAGE=en:Lunch\\, then\r\n  a walk\\nand\\N more\r\n",
+        b"ATTENDEE;CN=\"Doe, ^'J^'\";MEMBER=\"a:b\",c;RSVP=TRUE:mailto:j@x\r\n",
+        b"ATTACH:C:\\\\files\\\\a.txt\r\nRRULE:FREQ=DAILY;COUNT=2\r\nCATEGORIES:a\\,b,c\r\n",
+        b"DESCRIPTION;VALUE=TEXT:a\\;b\r\nX-FOO;VALUE=BINARY:a\\;b\r\n",
+        b"\r\n\r\nUID:1\r\n\r\nUID:2\r\n\r\n",
+        b"UID:1\nSUMMARY:a\r\n b\n c\r\nUID:2",
+        b"summary;language=en:x\r\nCOLOR:teal\r\nX-WR-CALNAME;X-FOO=\"a:b\":Work\r\n",
+        b"SUMMARY:caf\xe9\r\nCOMMENT;CN=\xe9:x\r\n",
+        b"SUMMARY;=x:y\r\nUID:1\r\nSUMMARY;CN:y\r\nUID:2\r\nATTENDEE;CN=\"x:y\r\nUID:3\r\nNOT-A-PROPERTY:x\r\n\
+          UID:4\r\n:x\r\nUID:5\r\nSUMMARY\r\nUID:6\r\nDESCRIPTION:\xff\r\nUID:7\r\n",
+        b"SUMMARY;CN\r\n =x;\r\n :y\r\nUID:1\r\n",
+        b"SUMMARY;LANGUAGE=en,de:x\r\nDESCRIPTION:\xc3\r\n \xa9\r\n",
+        b"",
+        b"\r\n",
+        b" \r\nUID\r\n",
+    ];
+
+    #[test]
+    fn agrees_with_buffered_lexer() {
+        let options = [
+            ParserOptions::strict(),
+            ParserOptions::default(),
+            ParserOptions::lenient(),
+            ParserOptions::strict().retain_raw(true),
+            ParserOptions::lenient().retain_raw(true),
+        ];
+        for input in INPUTS {
+            for options in &options {
+                compare(input, *options);
+            }
+        }
+    }
+
+    #[test]
+    fn agrees_on_arbitrary_input() {
+        // Every splice of two inputs, cut at every other octet, so that lines break in awkward places.
+        for a in INPUTS {
+            for b in INPUTS {
+                for cut in (0..a.len()).step_by(2) {
+                    let input = [&a[..cut], b].concat();
+                    compare(&input, ParserOptions::lenient().retain_raw(true));
+                    compare(&input, ParserOptions::default());
+                    compare(&input, ParserOptions::strict());
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn borrows_unless_changed() {
+        let input = b"SUMMARY;CN=x:plain\r\nDESCRIPTION:a\\, b\r\nCOMMENT:fol\r\n ded\r\nRRULE:FREQ=DAILY\r\n";
+        let mut lexer = Lexer::new(input);
+        let lines: Vec<_> = lexer.content_lines().map(Result::unwrap).collect();
+        assert!(matches!(lines[0].value, Cow::Borrowed("plain")));
+        assert!(matches!(lines[0].params[0].values[0], Cow::Borrowed("x")));
+        assert!(matches!((&lines[1].value, &lines[1].raw_value), (Cow::Owned(_), Cow::Borrowed("a\\, b"))));
+        assert_eq!(lines[1].value(), "a, b");
+        assert!(matches!(lines[2].value, Cow::Owned(_)));
+        assert_eq!(lines[2].value(), "folded");
+        assert!(matches!(lines[3].value, Cow::Borrowed("FREQ=DAILY")));
+    }
+}
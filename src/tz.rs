@@ -0,0 +1,713 @@
+//! Timezone definitions from VTIMEZONE components, and resolution of local times to UTC.
+//!
+//! Local times are resolved as RFC 5545 section 3.3.5 specifies: a local time that occurs twice (when
+//! the offset decreases, e.g. at the end of daylight saving time) refers to the first occurrence; a
+//! local time that does not occur (when the offset increases) is interpreted using the offset from
+//! before the gap, which yields an instant after the transition.
+//!
+//! With the `tzdb` feature, a TZID that the calendar does not define with a VTIMEZONE is looked up in
+//! the IANA timezone database. A VTIMEZONE in the calendar always takes precedence over the database,
+//! since it is the calendar's own definition of what the TZID means.
+//!
+//! TZIDs that are not IANA zone names are normalized before the lookup: Windows zone IDs such as
+//! "Pacific Standard Time", Outlook display names such as "(UTC+01:00) Amsterdam, Berlin, Bern, Rome,
+//! Stockholm, Vienna", Lotus Notes names such as "Z=-1$DO=1$DL=3 -1 1 10 -1 1$ZX=27$ZN=W. Europe", and
+//! vendor-prefixed names such as "/mozilla.org/20050126_1/America/New_York".
+//! Floating times are resolved in the calendar's X-WR-TIMEZONE, if it has one.
+
+use crate::component::{Component, IanaComponent};
+use crate::vevent::{self, DataType, DateTime, RdateValue, Rrule, VEventProperty, When};
+use crate::diagnostic::{detail, Diagnostic};
+use crate::{ContentLine, ErrorKind, IanaProperty, Position, Property, Span};
+use crate::windows_zones::{DISPLAY_REGIONS, WINDOWS_ZONES};
+use chrono::{Duration, FixedOffset, NaiveDateTime};
+use std::borrow::Cow;
+use std::sync::Mutex;
+
+#[derive(Debug)]
+enum Bad {
+    Property(Box<vevent::Error>),
+    Offset { invalid: String },
+    Missing { property: IanaProperty },
+    NoTzid,
+}
+
+#[derive(Debug)]
+pub struct Error {
+    bad: Bad,
+    span: Span,
+}
+impl Error {
+    pub fn kind(&self) -> ErrorKind {
+        match &self.bad {
+            Bad::Property(e) => e.kind(),
+            Bad::Offset { .. } => ErrorKind::BadValue(DataType::UtcOffset),
+            Bad::Missing { .. } | Bad::NoTzid => ErrorKind::Constraint,
+        }
+    }
+
+    pub fn line(&self) -> usize {
+        self.span.start().line()
+    }
+
+    pub fn position(&self) -> Position {
+        self.span.start()
+    }
+
+    pub fn span(&self) -> Span {
+        self.span
+    }
+
+    /// Render the error against the input it came from.
+    pub fn diagnostic<'s>(&self, source: &'s [u8]) -> Diagnostic<'s> {
+        Diagnostic::new(source, self.span, self.kind(), detail(|f| self.fmt_detail(f)))
+    }
+
+    pub(crate) fn fmt_detail(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        match &self.bad {
+            Bad::Property(e) => e.fmt_detail(f),
+            Bad::Offset { invalid } => write!(f, "invalid UTC offset {:?}", invalid),
+            Bad::Missing { property } => write!(f, "missing {}", property.as_str()),
+            Bad::NoTzid => write!(f, "VTIMEZONE requires TZID"),
+        }
+    }
+}
+impl std::fmt::Display for Error {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        write!(f, "{}: ", self.span.start())?;
+        self.fmt_detail(f)
+    }
+}
+impl std::error::Error for Error {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match &self.bad {
+            Bad::Property(e) => Some(&**e),
+            _ => None,
+        }
+    }
+}
+pub type Result<T> = std::result::Result<T, Error>;
+
+fn parse_offset(coli: &ContentLine) -> Result<FixedOffset> {
+    let s = coli.value();
+    let err = || Error { bad: Bad::Offset { invalid: s.to_owned() }, span: coli.value_span() };
+    let (sign, digits) = match s.as_bytes().first() {
+        Some(b'+') => (1, &s[1..]),
+        Some(b'-') => (-1, &s[1..]),
+        _ => return Err(err()),
+    };
+    if !(digits.len() == 4 || digits.len() == 6) || !digits.bytes().all(|c| c.is_ascii_digit()) {
+        return Err(err());
+    }
+    let field = |i: usize| digits.get(i..i + 2).map_or(0, |d| d.parse::<i32>().unwrap());
+    FixedOffset::east_opt(sign * (field(0) * 3600 + field(2) * 60 + field(4))).ok_or_else(err)
+}
+
+fn offset_duration(offset: FixedOffset) -> Duration {
+    Duration::seconds(offset.local_minus_utc().into())
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ObservanceKind {
+    Standard,
+    Daylight,
+}
+
+/// A STANDARD or DAYLIGHT subcomponent: a rule for when an offset comes into effect.
+#[derive(Debug)]
+pub struct Observance {
+    kind: ObservanceKind,
+    dtstart: NaiveDateTime,
+    offset_from: FixedOffset,
+    offset_to: FixedOffset,
+    rrule: Option<Rrule>,
+    rdates: Vec<When>,
+    names: Vec<String>,
+}
+
+impl Observance {
+    fn from_component(component: &Component, kind: ObservanceKind) -> Result<Self> {
+        let span = component.span();
+        let mut dtstart = None;
+        let mut offset_from = None;
+        let mut offset_to = None;
+        let mut rrule = None;
+        let mut rdates = Vec::new();
+        let mut names = Vec::new();
+        for coli in component.properties() {
+            match coli.name() {
+                Property::Iana(IanaProperty::Tzoffsetfrom) => offset_from = Some(parse_offset(coli)?),
+                Property::Iana(IanaProperty::Tzoffsetto) => offset_to = Some(parse_offset(coli)?),
+                Property::Iana(IanaProperty::Tzname) => names.push(coli.value().to_owned()),
+                _ => match vevent::parse_property(coli).map_err(|e| Error { span: e.span(), bad: Bad::Property(Box::new(e)) })? {
+                    Some(VEventProperty::Dtstart(when)) => dtstart = Some(when.naive()),
+                    Some(VEventProperty::Rrule(r)) => rrule = Some(r),
+                    Some(VEventProperty::Rdate(values)) => rdates.extend(values.iter().map(RdateValue::start)),
+                    _ => (),
+                },
+            }
+        }
+        let missing = |property| Error { bad: Bad::Missing { property }, span };
+        Ok(Observance {
+            kind,
+            dtstart: dtstart.ok_or_else(|| missing(IanaProperty::Dtstart))?,
+            offset_from: offset_from.ok_or_else(|| missing(IanaProperty::Tzoffsetfrom))?,
+            offset_to: offset_to.ok_or_else(|| missing(IanaProperty::Tzoffsetto))?,
+            rrule,
+            rdates,
+            names,
+        })
+    }
+
+    pub fn kind(&self) -> ObservanceKind {
+        self.kind
+    }
+
+    /// The local time at which the observance first takes effect.
+    pub fn dtstart(&self) -> NaiveDateTime {
+        self.dtstart
+    }
+
+    pub fn offset_from(&self) -> FixedOffset {
+        self.offset_from
+    }
+
+    pub fn offset_to(&self) -> FixedOffset {
+        self.offset_to
+    }
+
+    pub fn rrule(&self) -> Option<&Rrule> {
+        self.rrule.as_ref()
+    }
+
+    pub fn rdates(&self) -> &[When] {
+        &self.rdates
+    }
+
+    /// TZNAME values, e.g. "CET".
+    pub fn names(&self) -> impl Iterator<Item=&str> {
+        self.names.iter().map(|s| s.as_str())
+    }
+
+    /// Call `f` with the UTC instant of each onset of the observance up to `limit`. Onsets are in local
+    /// time before the observance, so a UTC UNTIL is compared with the local time less TZOFFSETFROM.
+    fn onsets(&self, limit: NaiveDateTime, mut f: impl FnMut(NaiveDateTime)) {
+        let from = offset_duration(self.offset_from);
+        let start = When::DateTime(DateTime::Floating(self.dtstart));
+        let locals: Box<dyn Iterator<Item=When>> = match &self.rrule {
+            Some(rrule) => Box::new(rrule.occurrences(&start).at_utc_offset(from)),
+            None => Box::new(std::iter::once(start.clone())),
+        };
+        for local in locals {
+            let utc = local.naive() - from;
+            if utc > limit {
+                break;
+            }
+            f(utc);
+        }
+        for rdate in &self.rdates {
+            let utc = rdate.naive() - from;
+            if utc <= limit {
+                f(utc);
+            }
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy)]
+struct Transition {
+    utc: NaiveDateTime,
+    from: FixedOffset,
+    to: FixedOffset,
+}
+
+/// Transitions are computed this far past the latest time asked about, so that converting the times of a
+/// recurrence set in order does not compute them again from DTSTART for each one.
+const TRANSITIONS_AHEAD_DAYS: i64 = 50 * 366;
+
+/// A timezone defined by a VTIMEZONE component.
+#[derive(Debug)]
+pub struct Timezone {
+    tzid: String,
+    observances: Vec<Observance>,
+    /// The transitions computed so far, and the UTC time up to which they are complete.
+    transitions: Mutex<(NaiveDateTime, Vec<Transition>)>,
+}
+
+impl Timezone {
+    pub fn from_component(component: &Component) -> Result<Self> {
+        let tzid = component.property(IanaProperty::Tzid)
+            .ok_or(Error { bad: Bad::NoTzid, span: component.span() })?
+            .value()
+            .to_owned();
+        let mut observances = Vec::new();
+        for child in component.children() {
+            if child.is(IanaComponent::Standard) {
+                observances.push(Observance::from_component(child, ObservanceKind::Standard)?);
+            } else if child.is(IanaComponent::Daylight) {
+                observances.push(Observance::from_component(child, ObservanceKind::Daylight)?);
+            }
+        }
+        Ok(Timezone { tzid, observances, transitions: Mutex::new((NaiveDateTime::MIN, Vec::new())) })
+    }
+
+    pub fn tzid(&self) -> &str {
+        &self.tzid
+    }
+
+    pub fn observances(&self) -> &[Observance] {
+        &self.observances
+    }
+
+    /// Call `f` with the transitions in order, including at least all those up to the UTC time `limit`.
+    fn with_transitions<T>(&self, limit: NaiveDateTime, f: impl FnOnce(&[Transition]) -> T) -> T {
+        let mut cache = self.transitions.lock().unwrap_or_else(|poisoned| poisoned.into_inner());
+        if cache.0 < limit {
+            let limit = limit.checked_add_signed(Duration::days(TRANSITIONS_AHEAD_DAYS)).unwrap_or(NaiveDateTime::MAX);
+            let mut transitions = Vec::new();
+            for o in &self.observances {
+                o.onsets(limit, |utc| transitions.push(Transition { utc, from: o.offset_from, to: o.offset_to }));
+            }
+            transitions.sort_by_key(|t| t.utc);
+            *cache = (limit, transitions);
+        }
+        f(&cache.1)
+    }
+
+    /// The offset in effect before the first transition.
+    fn initial_offset(&self) -> Option<FixedOffset> {
+        self.observances.iter().min_by_key(|o| o.dtstart).map(|o| o.offset_from)
+    }
+
+    fn offset_in(&self, transitions: &[Transition], utc: NaiveDateTime) -> Option<FixedOffset> {
+        match transitions[..transitions.partition_point(|t| t.utc <= utc)].last() {
+            Some(t) => Some(t.to),
+            None => self.initial_offset(),
+        }
+    }
+
+    /// The UTC offset in effect at the UTC time `utc`.
+    pub fn offset_at(&self, utc: NaiveDateTime) -> Option<FixedOffset> {
+        self.with_transitions(utc, |transitions| self.offset_in(transitions, utc))
+    }
+
+    /// The local time corresponding to the UTC time `utc`. Returns `None` if the timezone has no
+    /// observances, or the local time is out of range.
+    pub fn from_utc(&self, utc: NaiveDateTime) -> Option<NaiveDateTime> {
+        utc.checked_add_signed(offset_duration(self.offset_at(utc)?))
+    }
+
+    /// The UTC time corresponding to the local time `local`, with ambiguous and nonexistent local times
+    /// resolved as described in the module documentation. Returns `None` if the timezone has no
+    /// observances, or the UTC time is out of range.
+    pub fn to_utc(&self, local: NaiveDateTime) -> Option<NaiveDateTime> {
+        let initial = self.initial_offset()?;
+        // UTC offsets are less than a day, so only transitions within a day or two matter.
+        let window = Duration::days(2);
+        let from = local.checked_sub_signed(window).unwrap_or(NaiveDateTime::MIN);
+        let to = local.checked_add_signed(window).unwrap_or(NaiveDateTime::MAX);
+        let at = |utc: NaiveDateTime, offset: FixedOffset| utc.checked_add_signed(offset_duration(offset));
+        self.with_transitions(to, |transitions| {
+            let near = &transitions[transitions.partition_point(|t| t.utc < from)..transitions.partition_point(|t| t.utc <= to)];
+            let mut offsets: Vec<_> = near.iter().map(|t| t.to).collect();
+            offsets.push(self.offset_in(transitions, from).unwrap_or(initial));
+            // Valid interpretations; the earliest is the first occurrence of the local time.
+            let valid = offsets.iter()
+                .filter_map(|&o| Some((o, local.checked_sub_signed(offset_duration(o))?)))
+                .filter(|&(o, utc)| self.offset_in(transitions, utc) == Some(o))
+                .map(|(_, utc)| utc)
+                .min();
+            if valid.is_some() {
+                return valid;
+            }
+            // In a gap: use the offset from before the transition.
+            let gap = near.iter().find(|t| {
+                t.to.local_minus_utc() > t.from.local_minus_utc()
+                    && at(t.utc, t.from).is_some_and(|start| start <= local)
+                    && at(t.utc, t.to).is_some_and(|end| local < end)
+            });
+            let offset = match gap {
+                Some(t) => t.from,
+                None => self.offset_in(transitions, local)?,
+            };
+            local.checked_sub_signed(offset_duration(offset))
+        })
+    }
+}
+
+/// The timezones defined in a calendar, by TZID.
+#[derive(Debug, Default)]
+pub struct Timezones {
+    zones: Vec<Timezone>,
+    default_tzid: Option<String>,
+}
+
+impl Timezones {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Collect the VTIMEZONEs of a VCALENDAR, and its X-WR-TIMEZONE.
+    pub fn from_calendar(calendar: &Component) -> Result<Self> {
+        let mut zones = Timezones::new();
+        for tz in calendar.timezones() {
+            zones.insert(Timezone::from_component(tz)?);
+        }
+        zones.default_tzid = calendar.properties()
+            .find(|coli| coli.name().as_str().eq_ignore_ascii_case("X-WR-TIMEZONE"))
+            .map(|coli| coli.value().to_owned());
+        Ok(zones)
+    }
+
+    /// Add a timezone, replacing any with the same TZID.
+    pub fn insert(&mut self, tz: Timezone) {
+        self.zones.retain(|z| z.tzid != tz.tzid);
+        self.zones.push(tz);
+    }
+
+    pub fn get(&self, tzid: &str) -> Option<&Timezone> {
+        self.zones.iter().find(|z| z.tzid == tzid)
+    }
+
+    /// The TZID in which floating times are interpreted.
+    pub fn default_tzid(&self) -> Option<&str> {
+        self.default_tzid.as_deref()
+    }
+
+    pub fn set_default_tzid(&mut self, tzid: Option<String>) {
+        self.default_tzid = tzid;
+    }
+
+    /// The UTC time of a DATE-TIME. Returns `None` for floating times when there is no default TZID,
+    /// and for local times whose TZID is not defined (by the calendar, or with the `tzdb` feature, the
+    /// timezone database).
+    pub fn to_utc(&self, dt: &DateTime) -> Option<NaiveDateTime> {
+        match dt {
+            DateTime::Utc(utc) => Some(*utc),
+            DateTime::Local(local, tzid) => self.local_to_utc(*local, tzid),
+            DateTime::Floating(local) => self.local_to_utc(*local, self.default_tzid.as_deref()?),
+        }
+    }
+
+    /// The DATE-TIME of the same kind and TZID as `dt` at the UTC time `utc`. Returns `None` when `to_utc`
+    /// would.
+    pub fn at_utc(&self, dt: &DateTime, utc: NaiveDateTime) -> Option<DateTime> {
+        let local = match dt {
+            DateTime::Utc(_) => utc,
+            DateTime::Local(_, tzid) => self.utc_to_local(utc, tzid)?,
+            DateTime::Floating(_) => self.utc_to_local(utc, self.default_tzid.as_deref()?)?,
+        };
+        Some(dt.with_naive(local))
+    }
+
+    fn utc_to_local(&self, utc: NaiveDateTime, tzid: &str) -> Option<NaiveDateTime> {
+        if let Some(tz) = self.get(tzid) {
+            return tz.from_utc(utc);
+        }
+        let iana = iana_name(tzid)?;
+        match self.get(&iana) {
+            Some(tz) => tz.from_utc(utc),
+            None => tzdb_from_utc(&iana, utc),
+        }
+    }
+
+    fn local_to_utc(&self, local: NaiveDateTime, tzid: &str) -> Option<NaiveDateTime> {
+        if let Some(tz) = self.get(tzid) {
+            return tz.to_utc(local);
+        }
+        let iana = iana_name(tzid)?;
+        match self.get(&iana) {
+            Some(tz) => tz.to_utc(local),
+            None => tzdb_to_utc(&iana, local),
+        }
+    }
+}
+
+/// The IANA zone name for a TZID: the TZID itself if it is one, or the zone a Windows, Outlook, Lotus or
+/// vendor-specific name refers to. Without the `tzdb` feature, only the zones that Windows IDs map to
+/// are known.
+pub fn iana_name(tzid: &str) -> Option<Cow<'_, str>> {
+    let tzid = tzid.trim();
+    if is_iana_name(tzid) {
+        return Some(Cow::Borrowed(tzid));
+    }
+    if let Some(zone) = windows_zone(tzid) {
+        return Some(Cow::Borrowed(zone));
+    }
+    if let Some(zone) = lotus_zone(tzid) {
+        return Some(zone);
+    }
+    // Vendor prefixes, e.g. "/softwarestudio.org/Olson_20011030_5/America/New_York".
+    let slashes: Vec<_> = tzid.match_indices('/').map(|(i, _)| i).collect();
+    for &i in slashes.iter().rev().skip(1).take(2) {
+        if is_iana_name(&tzid[i + 1..]) {
+            return Some(Cow::Borrowed(&tzid[i + 1..]));
+        }
+    }
+    display_name_zone(tzid)
+}
+
+/// The default IANA zone of a Windows zone ID.
+fn windows_zone(id: &str) -> Option<&'static str> {
+    let (_, zones) = WINDOWS_ZONES.iter().find(|(windows, _)| windows.eq_ignore_ascii_case(id))?;
+    zones.split(' ').next()
+}
+
+fn is_iana_name(name: &str) -> bool {
+    known_zones().any(|zone| zone == name)
+}
+
+/// The zones named in the Windows mapping, followed by the timezone database if it is available.
+fn known_zones() -> impl Iterator<Item=&'static str> {
+    let windows = WINDOWS_ZONES.iter().flat_map(|(_, zones)| zones.split(' '));
+    #[cfg(feature = "tzdb")]
+    let windows = windows.chain(chrono_tz::TZ_VARIANTS.iter().map(|tz| tz.name()));
+    windows
+}
+
+/// Resolve a display name such as "(UTC-05:00) Eastern Time (US & Canada)" or "(GMT+01.00) Amsterdam /
+/// Berlin" by the cities it lists, or failing that by its offset.
+fn display_name_zone(name: &str) -> Option<Cow<'static, str>> {
+    let rest = name.strip_prefix('(')?;
+    let close = rest.find(')')?;
+    let (offset, places) = (&rest[..close], rest[close + 1..].trim());
+    if let Some((_, id)) = DISPLAY_REGIONS.iter().find(|(region, _)| region.eq_ignore_ascii_case(places)) {
+        return windows_zone(id).map(Cow::Borrowed);
+    }
+    for place in places.split([',', '/', ';']) {
+        let city = place.trim().replace(' ', "_");
+        if city.is_empty() {
+            continue;
+        }
+        let zone = known_zones().find(|zone| {
+            zone.rsplit('/').next().is_some_and(|last| last.eq_ignore_ascii_case(&city))
+        });
+        if let Some(zone) = zone {
+            return Some(Cow::Borrowed(zone));
+        }
+    }
+    let offset = offset.strip_prefix("UTC").or_else(|| offset.strip_prefix("GMT"))?;
+    if offset.is_empty() {
+        return Some(Cow::Borrowed("Etc/UTC"));
+    }
+    let (east, hhmm) = match offset.strip_prefix('+') {
+        Some(hhmm) => (true, hhmm),
+        None => (false, offset.strip_prefix('-')?),
+    };
+    let (hours, minutes) = hhmm.split_at(hhmm.find([':', '.'])?);
+    if minutes[1..] != *"00" {
+        return None;
+    }
+    let hours = i32::from(hours.parse::<u8>().ok()?);
+    etc_zone(if east { hours } else { -hours })
+}
+
+/// Resolve a Lotus Notes TZID such as "Z=5$DO=1$DL=3 2 1 11 1 1$ZX=37$ZN=Eastern" by its zone name (ZN,
+/// a Windows ID without " Standard Time"), or failing that, if it has no daylight saving time (DO=0), by
+/// its offset (Z, in hours west of UTC).
+fn lotus_zone(tzid: &str) -> Option<Cow<'static, str>> {
+    let field = |key: &str| tzid.split('$').find_map(|f| f.strip_prefix(key)?.strip_prefix('='));
+    let west = field("Z")?;
+    if let Some(name) = field("ZN") {
+        if let Some(zone) = windows_zone(name).or_else(|| windows_zone(&format!("{} Standard Time", name))) {
+            return Some(Cow::Borrowed(zone));
+        }
+    }
+    if field("DO") != Some("0") {
+        return None;
+    }
+    etc_zone(-west.parse::<i32>().ok()?)
+}
+
+/// The Etc zone a whole number of hours east of UTC. The signs of their names are inverted: Etc/GMT-1
+/// is an hour ahead of UTC.
+fn etc_zone(east: i32) -> Option<Cow<'static, str>> {
+    match east {
+        0 => Some(Cow::Borrowed("Etc/UTC")),
+        1..=14 => Some(Cow::Owned(format!("Etc/GMT-{}", east))),
+        -12..=-1 => Some(Cow::Owned(format!("Etc/GMT+{}", -east))),
+        _ => None,
+    }
+}
+
+#[cfg(feature = "tzdb")]
+fn tzdb_to_utc(tzid: &str, local: NaiveDateTime) -> Option<NaiveDateTime> {
+    use chrono::{LocalResult, Offset, TimeZone};
+    let tz: chrono_tz::Tz = tzid.parse().ok()?;
+    match tz.from_local_datetime(&local) {
+        LocalResult::Single(dt) => Some(dt.naive_utc()),
+        LocalResult::Ambiguous(first, _) => Some(first.naive_utc()),
+        LocalResult::None => {
+            // In a gap: use the offset from before the transition.
+            let before = tz.offset_from_utc_datetime(&local.checked_sub_signed(Duration::days(1))?).fix();
+            local.checked_sub_signed(offset_duration(before))
+        }
+    }
+}
+
+#[cfg(not(feature = "tzdb"))]
+fn tzdb_to_utc(_tzid: &str, _local: NaiveDateTime) -> Option<NaiveDateTime> {
+    None
+}
+
+#[cfg(feature = "tzdb")]
+fn tzdb_from_utc(tzid: &str, utc: NaiveDateTime) -> Option<NaiveDateTime> {
+    use chrono::{Offset, TimeZone};
+    let tz: chrono_tz::Tz = tzid.parse().ok()?;
+    utc.checked_add_signed(offset_duration(tz.offset_from_utc_datetime(&utc).fix()))
+}
+
+#[cfg(not(feature = "tzdb"))]
+fn tzdb_from_utc(_tzid: &str, _utc: NaiveDateTime) -> Option<NaiveDateTime> {
+    None
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::Lexer;
+
+    const CALENDAR: &str = "BEGIN:VCALENDAR\r\nX-WR-TIMEZONE:Central\r\nBEGIN:VTIMEZONE\r\nTZID:Central\r\n\
+                            BEGIN:STANDARD\r\nDTSTART:19701025T030000\r\nTZOFFSETFROM:+0200\r\nTZOFFSETTO:+0100\r\n\
+                            RRULE:FREQ=YEARLY;BYMONTH=10;BYDAY=-1SU\r\nTZNAME:CET\r\nEND:STANDARD\r\n\
+                            BEGIN:DAYLIGHT\r\nDTSTART:19700329T020000\r\nTZOFFSETFROM:+0100\r\nTZOFFSETTO:+0200\r\n\
+                            RRULE:FREQ=YEARLY;BYMONTH=3;BYDAY=-1SU;UNTIL=19800330T010000Z\r\nTZNAME:CEST\r\nEND:DAYLIGHT\r\n\
+                            END:VTIMEZONE\r\nEND:VCALENDAR\r\n";
+
+    fn zones() -> Timezones {
+        let calendar = Component::parse(&mut Lexer::new(CALENDAR.as_bytes())).unwrap().unwrap();
+        Timezones::from_calendar(&calendar).unwrap()
+    }
+
+    fn datetime(s: &str) -> NaiveDateTime {
+        NaiveDateTime::parse_from_str(s, "%Y%m%dT%H%M%S").unwrap()
+    }
+
+    fn hours(h: i32) -> Option<FixedOffset> {
+        FixedOffset::east_opt(h * 3600)
+    }
+
+    #[test]
+    fn observances() {
+        let zones = zones();
+        let tz = zones.get("Central").unwrap();
+        assert_eq!(tz.observances().len(), 2);
+        let daylight = &tz.observances()[1];
+        assert_eq!(daylight.kind(), ObservanceKind::Daylight);
+        assert_eq!(daylight.dtstart(), datetime("19700329T020000"));
+        assert_eq!(daylight.names().collect::<Vec<_>>(), ["CEST"]);
+        assert_eq!(Some(daylight.offset_to()), hours(2));
+    }
+
+    #[test]
+    fn offsets() {
+        let zones = zones();
+        let tz = zones.get("Central").unwrap();
+        assert_eq!(tz.offset_at(datetime("19790101T000000")), hours(1));
+        assert_eq!(tz.offset_at(datetime("19790701T000000")), hours(2));
+        // The transition at UNTIL itself is included.
+        assert_eq!(tz.offset_at(datetime("19800330T005959")), hours(1));
+        assert_eq!(tz.offset_at(datetime("19800330T010000")), hours(2));
+        assert_eq!(tz.offset_at(datetime("19810701T000000")), hours(1));
+        assert_eq!(tz.from_utc(datetime("19790701T120000")), Some(datetime("19790701T140000")));
+    }
+
+    #[test]
+    fn local_times() {
+        let zones = zones();
+        let tz = zones.get("Central").unwrap();
+        assert_eq!(tz.to_utc(datetime("19790115T120000")), Some(datetime("19790115T110000")));
+        assert_eq!(tz.to_utc(datetime("19790715T120000")), Some(datetime("19790715T100000")));
+        // An ambiguous time is the first occurrence; one in a gap uses the offset from before it.
+        assert_eq!(tz.to_utc(datetime("19791028T023000")), Some(datetime("19791028T003000")));
+        assert_eq!(tz.to_utc(datetime("19790325T023000")), Some(datetime("19790325T013000")));
+    }
+
+    #[test]
+    fn far_times() {
+        let zones = zones();
+        let tz = zones.get("Central").unwrap();
+        // Transitions computed for a later time still give an earlier one.
+        assert_eq!(tz.to_utc(datetime("99991231T120000")), Some(datetime("99991231T110000")));
+        assert_eq!(tz.to_utc(datetime("19790715T120000")), Some(datetime("19790715T100000")));
+        // Times that are out of range in UTC or local time.
+        assert_eq!(tz.to_utc(NaiveDateTime::MIN), None);
+        assert_eq!(tz.from_utc(NaiveDateTime::MAX), None);
+        assert_eq!(tz.to_utc(NaiveDateTime::MAX), Some(NaiveDateTime::MAX - Duration::hours(1)));
+        assert_eq!(zones.to_utc(&DateTime::Local(NaiveDateTime::MIN, "Central".to_owned())), None);
+    }
+
+    #[test]
+    fn date_times() {
+        let zones = zones();
+        let local = datetime("19790715T120000");
+        assert_eq!(zones.default_tzid(), Some("Central"));
+        assert_eq!(zones.to_utc(&DateTime::Utc(local)), Some(local));
+        assert_eq!(zones.to_utc(&DateTime::Local(local, "Central".to_owned())), Some(datetime("19790715T100000")));
+        assert_eq!(zones.to_utc(&DateTime::Floating(local)), Some(datetime("19790715T100000")));
+        assert_eq!(zones.to_utc(&DateTime::Local(local, "Nowhere/Special".to_owned())), None);
+        assert_eq!(Timezones::new().to_utc(&DateTime::Floating(local)), None);
+    }
+
+    #[test]
+    fn missing_offset() {
+        let input = CALENDAR.replace("TZOFFSETTO:+0100\r\n", "");
+        let calendar = Component::parse(&mut Lexer::new(input.as_bytes())).unwrap().unwrap();
+        assert!(Timezones::from_calendar(&calendar).is_err());
+    }
+
+    #[test]
+    fn names() {
+        let name = |tzid| iana_name(tzid).map(Cow::into_owned);
+        assert_eq!(name("America/New_York").as_deref(), Some("America/New_York"));
+        assert_eq!(name(" Pacific Standard Time ").as_deref(), Some("America/Los_Angeles"));
+        assert_eq!(name("/mozilla.org/20050126_1/America/New_York").as_deref(), Some("America/New_York"));
+        assert_eq!(name("(UTC+01:00) Amsterdam, Berlin, Bern, Rome, Stockholm, Vienna").as_deref(), Some("Europe/Amsterdam"));
+        assert_eq!(name("(UTC-05:00) Eastern Time (US & Canada)").as_deref(), Some("America/New_York"));
+        assert_eq!(name("(GMT+03.00) Somewhere").as_deref(), Some("Etc/GMT-3"));
+        assert_eq!(name("(UTC-08:00) Elsewhere").as_deref(), Some("Etc/GMT+8"));
+        assert_eq!(name("(UTC) Nowhere").as_deref(), Some("Etc/UTC"));
+        assert_eq!(name("(UTC+05:30) Elsewhere"), None);
+        assert_eq!(name("(UTCé) X"), None);
+        assert_eq!(name("(UTC+é:00) X"), None);
+        assert_eq!(name("Nowhere/Special"), None);
+    }
+
+    #[test]
+    fn lotus_names() {
+        let name = |tzid| iana_name(tzid).map(Cow::into_owned);
+        assert_eq!(name("Z=5$DO=1$DL=3 2 1 11 1 1$ZX=37$ZN=Eastern").as_deref(), Some("America/New_York"));
+        assert_eq!(name("Z=-1$DO=1$DL=3 -1 1 10 -1 1$ZX=27$ZN=W. Europe").as_deref(), Some("Europe/Berlin"));
+        assert_eq!(name("Z=-9$DO=0$ZX=60$ZN=Unknown").as_deref(), Some("Etc/GMT-9"));
+        // With daylight saving time, the offset alone does not identify a zone.
+        assert_eq!(name("Z=-9$DO=1$ZX=60$ZN=Unknown"), None);
+    }
+
+    #[cfg(feature = "tzdb")]
+    #[test]
+    fn database() {
+        let zones = zones();
+        let berlin = |s| DateTime::Local(datetime(s), "Europe/Berlin".to_owned());
+        assert_eq!(zones.to_utc(&berlin("20240115T120000")), Some(datetime("20240115T110000")));
+        assert_eq!(zones.to_utc(&berlin("20240715T120000")), Some(datetime("20240715T100000")));
+        assert_eq!(zones.to_utc(&berlin("20241027T023000")), Some(datetime("20241027T003000")));
+        assert_eq!(zones.to_utc(&berlin("20240331T023000")), Some(datetime("20240331T013000")));
+        // Names are normalized before the lookup.
+        let windows = DateTime::Local(datetime("20240115T120000"), "W. Europe Standard Time".to_owned());
+        assert_eq!(zones.to_utc(&windows), Some(datetime("20240115T110000")));
+        assert_eq!(zones.to_utc(&DateTime::Local(NaiveDateTime::MIN, "Europe/Berlin".to_owned())), None);
+        let max = DateTime::Local(NaiveDateTime::MAX, "Europe/Berlin".to_owned());
+        assert_eq!(zones.at_utc(&max, NaiveDateTime::MAX), None);
+    }
+
+    #[cfg(feature = "tzdb")]
+    #[test]
+    fn calendar_takes_precedence() {
+        let input = CALENDAR.replace("TZID:Central", "TZID:Europe/Berlin");
+        let calendar = Component::parse(&mut Lexer::new(input.as_bytes())).unwrap().unwrap();
+        let zones = Timezones::from_calendar(&calendar).unwrap();
+        // The calendar's definition has no daylight saving time after 1980.
+        let local = DateTime::Local(datetime("20240715T120000"), "Europe/Berlin".to_owned());
+        assert_eq!(zones.to_utc(&local), Some(datetime("20240715T110000")));
+    }
+}
@@ -1,12 +1,18 @@
 use crate::define_identifier_set;
-use chrono::{NaiveDate, NaiveDateTime};
-use crate::{ContentLine, Property, IanaProperty, IanaParam};
+use chrono::{NaiveDate, NaiveDateTime, NaiveTime};
+use crate::{ContentLine, ErrorKind, Param, ParamName, ParserOptions, Position, Property, IanaProperty, IanaParam, Span};
+use crate::{Warning, WarningCode};
+use crate::diagnostic::{detail, Diagnostic};
+use crate::component::{Component, IanaComponent};
+use crate::tz::Timezones;
+use crate::param::{Cutype, Fbtype, IanaCutype, IanaPartstat, IanaRole, Partstat, Range, Role};
+use std::ops::RangeInclusive;
 use std::str::FromStr;
 #[allow(unused)]
 use log::{debug, warn};
 
 // XXX: move TZID to wrapping type?
-#[derive(Debug)]
+#[derive(Debug, Clone, PartialEq, Eq)]
 pub enum DateTime {
     Utc(NaiveDateTime),
     Local(NaiveDateTime, String),
@@ -15,20 +21,416 @@ pub enum DateTime {
 impl DateTime {
     fn with_tzid(self, tzid: String) -> Maybe<Self> {
         match self {
-            DateTime::Utc(_) => return Err(Bad::Condition{ error: "UTC time must not have TZID".to_owned() }),
+            DateTime::Utc(_) => Err(Bad::Condition{ error: "UTC time must not have TZID".to_owned() }),
             DateTime::Floating(dt) => Ok(DateTime::Local(dt, tzid)),
             DateTime::Local(..) => panic!("set tzid twice on the same DateTime?"),
         }
     }
+
+    /// The wall-clock value, without regard to its timezone.
+    pub fn naive(&self) -> NaiveDateTime {
+        match self {
+            DateTime::Utc(dt) | DateTime::Local(dt, _) | DateTime::Floating(dt) => *dt,
+        }
+    }
+
+    /// A value of the same kind (and TZID), at a different wall-clock time.
+    pub(crate) fn with_naive(&self, dt: NaiveDateTime) -> Self {
+        match self {
+            DateTime::Utc(_) => DateTime::Utc(dt),
+            DateTime::Local(_, tzid) => DateTime::Local(dt, tzid.clone()),
+            DateTime::Floating(_) => DateTime::Floating(dt),
+        }
+    }
 }
 #[derive(Debug)]
 pub struct UtcDate(NaiveDateTime);
+impl UtcDate {
+    pub fn naive_utc(&self) -> NaiveDateTime {
+        self.0
+    }
+}
 
-#[derive(Debug)]
+#[derive(Debug, Clone, PartialEq, Eq)]
 pub enum When {
     Date(NaiveDate),
     DateTime(DateTime),
 }
+impl When {
+    /// The wall-clock value, without regard to its timezone; a DATE is taken as its midnight.
+    pub fn naive(&self) -> NaiveDateTime {
+        match self {
+            When::Date(d) => d.and_time(NaiveTime::MIN),
+            When::DateTime(dt) => dt.naive(),
+        }
+    }
+
+    /// A value of the same kind (and TZID), at a different wall-clock time.
+    pub(crate) fn with_naive(&self, dt: NaiveDateTime) -> Self {
+        match self {
+            When::Date(_) => When::Date(dt.date()),
+            When::DateTime(x) => When::DateTime(x.with_naive(dt)),
+        }
+    }
+}
+
+/// A DURATION value (RFC 5545 section 3.3.6). Weeks and days are nominal: they move the date, and keep the
+/// wall-clock time. Hours, minutes and seconds are exact: they are elapsed time, which differs from the
+/// change in wall-clock time when the UTC offset changes in between (see `When::checked_add_in`).
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub struct Duration {
+    negative: bool,
+    weeks: u32,
+    days: u32,
+    hours: u32,
+    minutes: u32,
+    seconds: u32,
+}
+impl Duration {
+    pub fn weeks(weeks: u32) -> Self {
+        Duration { weeks, ..Duration::default() }
+    }
+
+    pub fn days(days: u32) -> Self {
+        Duration { days, ..Duration::default() }
+    }
+
+    pub fn hms(hours: u32, minutes: u32, seconds: u32) -> Self {
+        Duration { hours, minutes, seconds, ..Duration::default() }
+    }
+
+    pub fn is_negative(&self) -> bool {
+        self.negative
+    }
+
+    /// The signed number of nominal days, counting each week as 7.
+    pub fn nominal_days(&self) -> i64 {
+        let days = i64::from(self.weeks) * 7 + i64::from(self.days);
+        if self.negative { -days } else { days }
+    }
+
+    /// The signed exact part: hours, minutes and seconds.
+    pub fn exact(&self) -> chrono::Duration {
+        let seconds = i64::from(self.hours) * 3600 + i64::from(self.minutes) * 60 + i64::from(self.seconds);
+        chrono::Duration::seconds(if self.negative { -seconds } else { seconds })
+    }
+}
+impl std::ops::Neg for Duration {
+    type Output = Duration;
+
+    fn neg(self) -> Duration {
+        Duration { negative: !self.negative, ..self }
+    }
+}
+impl FromStr for Duration {
+    type Err = ();
+    fn from_str(s: &str) -> std::result::Result<Self, Self::Err> {
+        let (negative, rest) = match s.as_bytes().first() {
+            Some(b'-') => (true, &s[1..]),
+            Some(b'+') => (false, &s[1..]),
+            _ => (false, s),
+        };
+        let mut rest = rest.strip_prefix('P').ok_or(())?;
+        let mut d = Duration { negative, ..Duration::default() };
+        // Components must appear in this order; each is optional, but there must be at least one, and at
+        // least one after a T.
+        let (mut found, mut in_time, mut found_time) = (false, false, false);
+        for (unit, time) in [('W', false), ('D', false), ('H', true), ('M', true), ('S', true)] {
+            if time && !in_time {
+                match rest.strip_prefix('T') {
+                    Some(r) => {
+                        rest = r;
+                        in_time = true;
+                    }
+                    None => break,
+                }
+            }
+            let digits = rest.bytes().take_while(u8::is_ascii_digit).count();
+            if digits == 0 || !rest[digits..].starts_with(unit) {
+                continue;
+            }
+            let n = rest[..digits].parse().map_err(|_| ())?;
+            rest = &rest[digits + 1..];
+            found = true;
+            found_time |= time;
+            match unit {
+                'W' => d.weeks = n,
+                'D' => d.days = n,
+                'H' => d.hours = n,
+                'M' => d.minutes = n,
+                _ => d.seconds = n,
+            }
+        }
+        if !found || !rest.is_empty() || in_time != found_time {
+            return Err(());
+        }
+        Ok(d)
+    }
+}
+impl std::fmt::Display for Duration {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        if self.negative {
+            f.write_str("-")?;
+        }
+        f.write_str("P")?;
+        let time = self.hours != 0 || self.minutes != 0 || self.seconds != 0;
+        // A week count cannot be combined with other components.
+        if self.weeks != 0 && self.days == 0 && !time {
+            return write!(f, "{}W", self.weeks);
+        }
+        let days = u64::from(self.weeks) * 7 + u64::from(self.days);
+        if days != 0 {
+            write!(f, "{}D", days)?;
+        }
+        if !time {
+            return if days == 0 { f.write_str("T0S") } else { Ok(()) };
+        }
+        f.write_str("T")?;
+        if self.hours != 0 {
+            write!(f, "{}H", self.hours)?;
+        }
+        // Minutes are required between hours and seconds.
+        if self.minutes != 0 || (self.hours != 0 && self.seconds != 0) {
+            write!(f, "{}M", self.minutes)?;
+        }
+        if self.seconds != 0 {
+            write!(f, "{}S", self.seconds)?;
+        }
+        Ok(())
+    }
+}
+
+impl When {
+    /// The time `d` later in wall-clock terms, or `None` if out of range: both parts of the duration are added
+    /// to the wall-clock time, as if it were floating. This is only correct for UTC and floating times, and
+    /// for local times when the UTC offset does not change in between; `checked_add_in` takes the timezone
+    /// into account. A DATE plus a duration with an exact part is a floating DATE-TIME.
+    pub fn checked_add(&self, d: &Duration) -> Option<When> {
+        let t = self.naive()
+            .checked_add_signed(chrono::Duration::try_days(d.nominal_days())?)?
+            .checked_add_signed(d.exact())?;
+        Some(match self {
+            When::Date(_) if d.exact().is_zero() => When::Date(t.date()),
+            When::Date(_) => When::DateTime(DateTime::Floating(t)),
+            When::DateTime(dt) => When::DateTime(dt.with_naive(t)),
+        })
+    }
+
+    /// The time `d` later (RFC 5545 section 3.3.6): nominal days are added to the wall-clock date, and the exact
+    /// part is added as elapsed time, in UTC. Returns `None` if out of range, or if a local or floating time
+    /// cannot be resolved with `zones`; a UTC time or a DATE does not need to be.
+    pub fn checked_add_in(&self, d: &Duration, zones: &Timezones) -> Option<When> {
+        let days = self.checked_add(&Duration { hours: 0, minutes: 0, seconds: 0, ..*d })?;
+        match days {
+            When::DateTime(dt) if !d.exact().is_zero() && !matches!(self, When::Date(_)) => {
+                let utc = zones.to_utc(&dt)?.checked_add_signed(d.exact())?;
+                Some(When::DateTime(zones.at_utc(&dt, utc)?))
+            }
+            _ => self.checked_add(d),
+        }
+    }
+}
+/// Panics if the result is out of range; see `When::checked_add`.
+impl std::ops::Add<Duration> for When {
+    type Output = When;
+
+    fn add(self, d: Duration) -> When {
+        self.checked_add(&d).expect("`When + Duration` out of range")
+    }
+}
+/// Panics if the result is out of range; see `When::checked_add`.
+impl std::ops::Sub<Duration> for When {
+    type Output = When;
+
+    fn sub(self, d: Duration) -> When {
+        self.checked_add(&-d).expect("`When - Duration` out of range")
+    }
+}
+
+/// A PERIOD value (RFC 5545 section 3.3.9): a start, and either an end or a duration.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Period {
+    start: DateTime,
+    end: PeriodEnd,
+}
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum PeriodEnd {
+    End(DateTime),
+    Duration(Duration),
+}
+impl Period {
+    pub fn new(start: DateTime, end: PeriodEnd) -> Self {
+        Period { start, end }
+    }
+
+    pub fn start(&self) -> &DateTime {
+        &self.start
+    }
+
+    /// The end as given: explicit, or as a duration.
+    pub fn end(&self) -> &PeriodEnd {
+        &self.end
+    }
+
+    /// The end, computed from the duration if necessary; `None` if out of range.
+    pub fn end_time(&self) -> Option<DateTime> {
+        match &self.end {
+            PeriodEnd::End(end) => Some(end.clone()),
+            PeriodEnd::Duration(d) => match When::DateTime(self.start.clone()).checked_add(d)? {
+                When::DateTime(end) => Some(end),
+                When::Date(_) => None,
+            },
+        }
+    }
+}
+
+/// A value of an RDATE: a DATE or DATE-TIME, or a PERIOD.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum RdateValue {
+    When(When),
+    Period(Period),
+}
+impl RdateValue {
+    /// The start of the instance this adds to a recurrence set.
+    pub fn start(&self) -> When {
+        match self {
+            RdateValue::When(when) => when.clone(),
+            RdateValue::Period(period) => When::DateTime(period.start.clone()),
+        }
+    }
+
+    pub fn period(&self) -> Option<&Period> {
+        match self {
+            RdateValue::When(_) => None,
+            RdateValue::Period(period) => Some(period),
+        }
+    }
+}
+
+/// An ATTENDEE or ORGANIZER (RFC 5545 sections 3.8.4.1 and 3.8.4.3): a CAL-ADDRESS, usually a mailto:
+/// URI, with the parameters that describe the participant.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct CalAddress {
+    uri: String,
+    cn: Option<String>,
+    cutype: Option<Cutype>,
+    role: Option<Role>,
+    partstat: Option<Partstat>,
+    rsvp: Option<bool>,
+    delegated_from: Vec<String>,
+    delegated_to: Vec<String>,
+    sent_by: Option<String>,
+    dir: Option<String>,
+    member: Vec<String>,
+    language: Option<String>,
+    /// Parameters not listed above, e.g. x-params, kept for writing.
+    other_params: Vec<Param>,
+}
+impl CalAddress {
+    pub fn new(uri: String) -> Self {
+        CalAddress {
+            uri,
+            cn: None,
+            cutype: None,
+            role: None,
+            partstat: None,
+            rsvp: None,
+            delegated_from: Vec::new(),
+            delegated_to: Vec::new(),
+            sent_by: None,
+            dir: None,
+            member: Vec::new(),
+            language: None,
+            other_params: Vec::new(),
+        }
+    }
+
+    pub fn uri(&self) -> &str {
+        &self.uri
+    }
+
+    /// The address of a mailto: URI.
+    pub fn email(&self) -> Option<&str> {
+        let scheme = self.uri.get(..7)?;
+        if scheme.eq_ignore_ascii_case("mailto:") { Some(&self.uri[7..]) } else { None }
+    }
+
+    /// The common name (CN).
+    pub fn cn(&self) -> Option<&str> {
+        self.cn.as_deref()
+    }
+
+    /// The CUTYPE; INDIVIDUAL if not specified.
+    pub fn cutype(&self) -> Cutype {
+        self.cutype.clone().unwrap_or(Cutype::Iana(IanaCutype::Individual))
+    }
+
+    /// The ROLE; REQ-PARTICIPANT if not specified.
+    pub fn role(&self) -> Role {
+        self.role.clone().unwrap_or(Role::Iana(IanaRole::ReqParticipant))
+    }
+
+    /// The PARTSTAT; NEEDS-ACTION if not specified.
+    pub fn partstat(&self) -> Partstat {
+        self.partstat.clone().unwrap_or(Partstat::Iana(IanaPartstat::NeedsAction))
+    }
+
+    /// Whether a reply is requested (RSVP); false if not specified.
+    pub fn rsvp(&self) -> bool {
+        self.rsvp.unwrap_or(false)
+    }
+
+    pub fn delegated_from(&self) -> impl Iterator<Item=&str> {
+        self.delegated_from.iter().map(|s| s.as_str())
+    }
+
+    pub fn delegated_to(&self) -> impl Iterator<Item=&str> {
+        self.delegated_to.iter().map(|s| s.as_str())
+    }
+
+    pub fn sent_by(&self) -> Option<&str> {
+        self.sent_by.as_deref()
+    }
+
+    /// The directory entry (DIR), a URI.
+    pub fn dir(&self) -> Option<&str> {
+        self.dir.as_deref()
+    }
+
+    /// The groups the participant is a member of.
+    pub fn member(&self) -> impl Iterator<Item=&str> {
+        self.member.iter().map(|s| s.as_str())
+    }
+
+    pub fn language(&self) -> Option<&str> {
+        self.language.as_deref()
+    }
+
+    pub fn other_params(&self) -> &[Param] {
+        &self.other_params
+    }
+
+    pub fn set_cn(&mut self, cn: Option<String>) {
+        self.cn = cn;
+    }
+
+    pub fn set_cutype(&mut self, cutype: Option<Cutype>) {
+        self.cutype = cutype;
+    }
+
+    pub fn set_role(&mut self, role: Option<Role>) {
+        self.role = role;
+    }
+
+    pub fn set_partstat(&mut self, partstat: Option<Partstat>) {
+        self.partstat = partstat;
+    }
+
+    pub fn set_rsvp(&mut self, rsvp: Option<bool>) {
+        self.rsvp = rsvp;
+    }
+}
+
 impl From<NaiveDate> for When {
     fn from(d: NaiveDate) -> Self {
         When::Date(d)
@@ -52,18 +454,28 @@ pub enum VEventProperty {
     Description(String),
     Comment(String),
     Status(Status),
-    RecurrenceId(When),
+    RecurrenceId(When, Option<Range>),
     Location(String),
     Sequence(u32),
     Transp(Transp),
     Dtstamp(UtcDate),
     Created(UtcDate),
     LastModified(UtcDate),
-    Exdate(When),
+    Exdate(Vec<When>),
+    Rdate(Vec<RdateValue>),
+    Exrule(Rrule),
+    Categories(Vec<String>),
+    Resources(Vec<String>),
+    Duration(Duration),
+    /// The FBTYPE parameter, if given, and the periods.
+    Freebusy(Option<Fbtype>, Vec<Period>),
+    Attendee(CalAddress),
+    Organizer(CalAddress),
 }
 
-#[derive(Debug)]
-enum DataType {
+/// The kinds of value that can fail to parse.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum DataType {
     Date,
     DateTime,
     Rrule,
@@ -71,6 +483,29 @@ enum DataType {
     Int,
     Transp,
     UtcDate,
+    Range,
+    UtcOffset,
+    Duration,
+    Period,
+    Boolean,
+}
+impl std::fmt::Display for DataType {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        f.write_str(match self {
+            DataType::Date => "DATE",
+            DataType::DateTime => "DATE-TIME",
+            DataType::Rrule => "RECUR",
+            DataType::Status => "STATUS",
+            DataType::Int => "INTEGER",
+            DataType::Transp => "TRANSP",
+            DataType::UtcDate => "UTC DATE-TIME",
+            DataType::Range => "RANGE",
+            DataType::UtcOffset => "UTC-OFFSET",
+            DataType::Duration => "DURATION",
+            DataType::Period => "PERIOD",
+            DataType::Boolean => "BOOLEAN",
+        })
+    }
 }
 
 #[derive(Debug)]
@@ -81,19 +516,58 @@ enum Bad {
 #[derive(Debug)]
 pub struct Error {
     bad: Bad,
-    line: usize,
+    span: Span,
+}
+impl Error {
+    pub fn kind(&self) -> ErrorKind {
+        match self.bad {
+            Bad::Value { datatype, .. } => ErrorKind::BadValue(datatype),
+            Bad::Condition { .. } => ErrorKind::Constraint,
+        }
+    }
+
+    pub fn line(&self) -> usize {
+        self.span.start().line()
+    }
+
+    pub fn position(&self) -> Position {
+        self.span.start()
+    }
+
+    pub fn span(&self) -> Span {
+        self.span
+    }
+
+    /// Render the error against the input it came from.
+    pub fn diagnostic<'s>(&self, source: &'s [u8]) -> Diagnostic<'s> {
+        Diagnostic::new(source, self.span, self.kind(), detail(|f| self.fmt_detail(f)))
+    }
+
+    pub(crate) fn fmt_detail(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        match &self.bad {
+            Bad::Value { datatype, invalid } => write!(f, "invalid {} value {:?}", datatype, invalid),
+            Bad::Condition { error } => write!(f, "{}", error),
+        }
+    }
 }
+impl std::fmt::Display for Error {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        write!(f, "{}: ", self.span.start())?;
+        self.fmt_detail(f)
+    }
+}
+impl std::error::Error for Error {}
 type Maybe<T> = std::result::Result<T, Bad>;
 pub type Result<T> = std::result::Result<T, Error>;
 
 fn parse_date(s: &str) -> Maybe<NaiveDate> {
     let err = || Bad::Value { datatype: DataType::Date, invalid: s.to_owned() };
-    if s.len() != 8 { return Err(err()); }
+    if s.len() != 8 || !s.bytes().all(|c| c.is_ascii_digit()) { return Err(err()); }
     let err = |_| Bad::Value { datatype: DataType::Date, invalid: s.to_owned() };
     let yy = i32::from_str(&s[0..4]).map_err(err)?;
     let mm = u32::from_str(&s[4..6]).map_err(err)?;
     let dd = u32::from_str(&s[6..8]).map_err(err)?;
-    Ok(NaiveDate::from_ymd(yy, mm, dd))
+    NaiveDate::from_ymd_opt(yy, mm, dd).ok_or(Bad::Value { datatype: DataType::Date, invalid: s.to_owned() })
 }
 
 fn parse_datetime(s: &str) -> Maybe<DateTime> {
@@ -102,14 +576,14 @@ fn parse_datetime(s: &str) -> Maybe<DateTime> {
     let date = parts.next().unwrap();
     let date = parse_date(date).map_err(|_| err())?;
     let time = parts.next().ok_or_else(err)?;
-    if time.len() < 6 { return Err(err()); }
+    if !time.as_bytes().get(..6).is_some_and(|hms| hms.iter().all(u8::is_ascii_digit)) { return Err(err()); }
     let (value, z) = time.split_at(6);
     let err = |_| Bad::Value { datatype: DataType::DateTime, invalid: s.to_owned() };
     let hh = u32::from_str(&value[0..2]).map_err(err)?;
     let mm = u32::from_str(&value[2..4]).map_err(err)?;
     let ss = u32::from_str(&value[4..6]).map_err(err)?;
-    let datetime = date.and_hms(hh, mm, ss);
     let err = || Bad::Value { datatype: DataType::DateTime, invalid: s.to_owned() };
+    let datetime = date.and_hms_opt(hh, mm, ss).ok_or_else(err)?;
     Ok(match z {
         "Z" => DateTime::Utc(datetime),
         "" => DateTime::Floating(datetime),
@@ -117,29 +591,100 @@ fn parse_datetime(s: &str) -> Maybe<DateTime> {
     })
 }
 
-fn parse_utc(s: &str) -> Maybe<UtcDate> {
-    let t = parse_datetime(s)?;
-    if let DateTime::Utc(utc) = t {
-        Ok(UtcDate(utc))
-    } else {
-        Err(Bad::Value { datatype: DataType::UtcDate, invalid: s.to_owned() })
+/// Parse a DATE-TIME that must be UTC; a local time is taken as UTC, with a warning.
+fn parse_utc(coli: &ContentLine, options: &ParserOptions, warnings: &mut Vec<Warning>) -> Maybe<UtcDate> {
+    Ok(match parse_datetime(coli.value())? {
+        DateTime::Utc(utc) => UtcDate(utc),
+        _ if !options.lenient_values => return Err(Bad::Value { datatype: DataType::UtcDate, invalid: coli.value().to_owned() }),
+        t => {
+            let message = format!("{} must be in UTC; taking {} as UTC", coli.name().as_str(), coli.value());
+            warnings.push(Warning::new(WarningCode::NotUtc, coli.value_span(), message));
+            UtcDate(t.naive())
+        }
+    })
+}
+
+/// Parse a DATE-TIME, in the zone `tzid` if given.
+fn parse_local(s: &str, tzid: Option<&str>) -> Maybe<DateTime> {
+    let dt = parse_datetime(s)?;
+    match tzid {
+        Some(tzid) => dt.with_tzid(tzid.to_owned()),
+        None => Ok(dt),
     }
 }
 
 fn parse_when(coli: &ContentLine) -> Maybe<When> {
     Ok(match coli.value_of(IanaParam::Value) {
         Some("DATE") => parse_date(coli.value())?.into(),
-        _ => {
-            let tzid = coli.value_of(IanaParam::Tzid);
-            let mut dt = parse_datetime(coli.value())?;
-            if let Some(tzid) = tzid {
-                dt = dt.with_tzid(tzid.to_owned())?;
-            }
-            dt.into()
-        }
+        _ => parse_local(coli.value(), coli.value_of(IanaParam::Tzid))?.into(),
     })
 }
 
+/// Parse comma-separated DATE or DATE-TIME values, sharing the VALUE and TZID parameters.
+fn parse_when_list(coli: &ContentLine) -> Maybe<Vec<When>> {
+    let tzid = coli.value_of(IanaParam::Tzid);
+    coli.value().split(',').map(|s| Ok(match coli.value_of(IanaParam::Value) {
+        Some("DATE") => parse_date(s)?.into(),
+        _ => parse_local(s, tzid)?.into(),
+    })).collect()
+}
+
+fn parse_period(s: &str, tzid: Option<&str>) -> Maybe<Period> {
+    let err = || Bad::Value { datatype: DataType::Period, invalid: s.to_owned() };
+    let (start, end) = s.split_once('/').ok_or_else(err)?;
+    let start = parse_local(start, tzid).map_err(|_| err())?;
+    let end = if end.starts_with(['+', '-', 'P']) {
+        PeriodEnd::Duration(end.parse().map_err(|_| err())?)
+    } else {
+        PeriodEnd::End(parse_local(end, tzid).map_err(|_| err())?)
+    };
+    // The end must not be before the start. Times of different kinds cannot be compared without timezones.
+    let backwards = match (&start, &end) {
+        (_, PeriodEnd::Duration(d)) => d.is_negative(),
+        (DateTime::Utc(start), PeriodEnd::End(DateTime::Utc(end))) |
+        (DateTime::Local(start, _), PeriodEnd::End(DateTime::Local(end, _))) |
+        (DateTime::Floating(start), PeriodEnd::End(DateTime::Floating(end))) => end < start,
+        _ => false,
+    };
+    if backwards {
+        return Err(err());
+    }
+    Ok(Period { start, end })
+}
+
+/// Parse the comma-separated periods of a FREEBUSY, which must be in UTC.
+fn parse_freebusy(coli: &ContentLine, options: &ParserOptions, warnings: &mut Vec<Warning>) -> Maybe<Vec<Period>> {
+    coli.value().split(',').map(|s| {
+        let period = parse_period(s, None)?;
+        let end_utc = match &period.end {
+            PeriodEnd::End(end) => matches!(end, DateTime::Utc(_)),
+            PeriodEnd::Duration(_) => true,
+        };
+        if end_utc && matches!(period.start, DateTime::Utc(_)) {
+            return Ok(period);
+        }
+        if !options.lenient_values {
+            return Err(Bad::Value { datatype: DataType::Period, invalid: s.to_owned() });
+        }
+        let message = format!("FREEBUSY periods must be in UTC; taking {} as UTC", s);
+        warnings.push(Warning::new(WarningCode::NotUtc, coli.value_span(), message));
+        let end = match period.end {
+            PeriodEnd::End(end) => PeriodEnd::End(DateTime::Utc(end.naive())),
+            end => end,
+        };
+        Ok(Period { start: DateTime::Utc(period.start.naive()), end })
+    }).collect()
+}
+
+/// Parse the comma-separated values of an RDATE, of the type given by the VALUE parameter.
+fn parse_rdate(coli: &ContentLine) -> Maybe<Vec<RdateValue>> {
+    if coli.value_of(IanaParam::Value) != Some("PERIOD") {
+        return Ok(parse_when_list(coli)?.into_iter().map(RdateValue::When).collect());
+    }
+    let tzid = coli.value_of(IanaParam::Tzid);
+    coli.value().split(',').map(|s| Ok(RdateValue::Period(parse_period(s, tzid)?))).collect()
+}
+
 define_identifier_set!(Transp,
     Transparent, b"TRANSPARENT",
     Opaque,      b"OPAQUE",
@@ -170,19 +715,19 @@ define_identifier_set!(Weekday,
     Su, b"SU",
 );
 #[derive(Debug)]
-enum Until {
+pub(crate) enum Until {
     Date(NaiveDate),
     DateTime(DateTime),
 }
 #[derive(Debug)]
-enum Stop {
+pub(crate) enum Stop {
     Until(Until),
     Count(u32),
 }
 #[derive(Debug)]
-struct WeekdayNum {
-    wday: Weekday,
-    num: Option<i8>,
+pub(crate) struct WeekdayNum {
+    pub(crate) wday: Weekday,
+    pub(crate) num: Option<i8>,
 }
 impl FromStr for WeekdayNum {
     type Err = ();
@@ -202,19 +747,19 @@ impl FromStr for WeekdayNum {
 }
 #[derive(Debug)]
 pub struct Rrule {
-    freq: Freq,
-    stop: Option<Stop>,
-    interval: Option<u32>,
-    wkst: Option<Weekday>,
-    bysecond: Option<Vec<u8>>,
-    byminute: Option<Vec<u8>>,
-    byhour: Option<Vec<u8>>,
-    bymonth: Option<Vec<u8>>,
-    byyearday: Option<Vec<i32>>,
-    bymonthday: Option<Vec<i32>>,
-    byweekno: Option<Vec<i32>>,
-    bysetpos: Option<Vec<u32>>,
-    byday: Option<Vec<WeekdayNum>>,
+    pub(crate) freq: Freq,
+    pub(crate) stop: Option<Stop>,
+    pub(crate) interval: Option<u32>,
+    pub(crate) wkst: Option<Weekday>,
+    pub(crate) bysecond: Option<Vec<u8>>,
+    pub(crate) byminute: Option<Vec<u8>>,
+    pub(crate) byhour: Option<Vec<u8>>,
+    pub(crate) bymonth: Option<Vec<u8>>,
+    pub(crate) byyearday: Option<Vec<i32>>,
+    pub(crate) bymonthday: Option<Vec<i32>>,
+    pub(crate) byweekno: Option<Vec<i32>>,
+    pub(crate) bysetpos: Option<Vec<i32>>,
+    pub(crate) byday: Option<Vec<WeekdayNum>>,
 }
 
 fn parse_until(s: &str) -> Maybe<Until> {
@@ -229,6 +774,14 @@ fn parse_comma_list<X: FromStr>(s: &str) -> std::result::Result<Vec<X>, X::Err>
     s.split(',').map(|v| FromStr::from_str(v)).collect()
 }
 
+/// Whether every value is in `range`, or (if `signed`) its negation is.
+fn in_range<X: Copy + Into<i32>>(values: &Option<Vec<X>>, range: RangeInclusive<i32>, signed: bool) -> bool {
+    values.iter().flatten().all(|&v| {
+        let v = v.into();
+        range.contains(&v) || signed && range.contains(&-v)
+    })
+}
+
 fn parse_rrule(coli: &ContentLine) -> Maybe<Rrule> {
     let spec = coli.value();
     let err = || Bad::Value { datatype: DataType::Rrule, invalid: spec.to_owned() };
@@ -267,8 +820,21 @@ fn parse_rrule(coli: &ContentLine) -> Maybe<Rrule> {
             _ => return Err(err()),
         }
     }
+    let byday_nums = byday.as_ref().map(|days: &Vec<WeekdayNum>| days.iter().filter_map(|d| d.num).collect());
+    let valid = in_range(&bysecond, 0..=59, false)
+        && in_range(&byminute, 0..=59, false)
+        && in_range(&byhour, 0..=23, false)
+        && in_range(&bymonth, 1..=12, false)
+        && in_range(&byyearday, 1..=366, true)
+        && in_range(&bymonthday, 1..=31, true)
+        && in_range(&byweekno, 1..=53, true)
+        && in_range(&bysetpos, 1..=366, true)
+        && in_range::<i8>(&byday_nums, 1..=53, true);
+    if !valid {
+        return Err(err());
+    }
     Ok(Rrule {
-        freq: freq.unwrap(),
+        freq: freq.ok_or_else(err)?,
         stop,
         interval,
         wkst,
@@ -284,53 +850,1060 @@ fn parse_rrule(coli: &ContentLine) -> Maybe<Rrule> {
     })
 }
 
+/// Split a TEXT list on the commas that are not escaped, and unescape the items. An empty value is an
+/// empty list.
+fn parse_text_list(coli: &ContentLine) -> Vec<String> {
+    let raw = coli.raw_value();
+    if raw.is_empty() {
+        return Vec::new();
+    }
+    let mut items = Vec::new();
+    let mut start = 0;
+    let mut escaped = false;
+    for (i, c) in raw.char_indices() {
+        match c {
+            _ if escaped => escaped = false,
+            '\\' => escaped = true,
+            ',' => {
+                items.push(&raw[start..i]);
+                start = i + 1;
+            }
+            _ => (),
+        }
+    }
+    items.push(&raw[start..]);
+    items.into_iter().map(|item| crate::unescape_text(item).unwrap_or_else(|| item.to_owned())).collect()
+}
+
+fn parse_boolean(s: &str) -> Maybe<bool> {
+    if s.eq_ignore_ascii_case("TRUE") {
+        Ok(true)
+    } else if s.eq_ignore_ascii_case("FALSE") {
+        Ok(false)
+    } else {
+        Err(Bad::Value { datatype: DataType::Boolean, invalid: s.to_owned() })
+    }
+}
+
+fn parse_cal_address(coli: &ContentLine, options: &ParserOptions, warnings: &mut Vec<Warning>) -> Maybe<CalAddress> {
+    let mut address = CalAddress::new(coli.value().to_owned());
+    for param in coli.params() {
+        let iana = match param.name() {
+            ParamName::Iana(iana) => *iana,
+            _ => {
+                address.other_params.push(param.clone());
+                continue;
+            }
+        };
+        let first = param.values().next().unwrap_or("");
+        let all = || param.values().map(|s| s.to_owned()).collect();
+        match iana {
+            IanaParam::Cn => address.cn = Some(first.to_owned()),
+            IanaParam::Cutype => address.cutype = Some(Cutype::from_value(first)),
+            IanaParam::Role => address.role = Some(Role::from_value(first)),
+            IanaParam::Partstat => address.partstat = Some(Partstat::from_value(first)),
+            IanaParam::Rsvp => match parse_boolean(first) {
+                Ok(rsvp) => address.rsvp = Some(rsvp),
+                Err(bad) if !options.lenient_values => return Err(bad),
+                // Kept as it was, for writing.
+                Err(_) => {
+                    let message = format!("RSVP must be TRUE or FALSE; ignoring {}", first);
+                    warnings.push(Warning::new(WarningCode::InvalidValue, param.span(), message));
+                    address.other_params.push(param.clone());
+                }
+            },
+            IanaParam::DelegatedFrom => address.delegated_from = all(),
+            IanaParam::DelegatedTo => address.delegated_to = all(),
+            IanaParam::SentBy => address.sent_by = Some(first.to_owned()),
+            IanaParam::Dir => address.dir = Some(first.to_owned()),
+            IanaParam::Member => address.member = all(),
+            IanaParam::Language => address.language = Some(first.to_owned()),
+            _ => address.other_params.push(param.clone()),
+        }
+    }
+    Ok(address)
+}
+
+/// The RANGE of a RECURRENCE-ID. RFC 5545 allows only THISANDFUTURE; any other value (such as the
+/// THISANDPRIOR of RFC 2445) is kept with a warning if `options` allow.
+fn parse_range(coli: &ContentLine, options: &ParserOptions, warnings: &mut Vec<Warning>) -> Maybe<Option<Range>> {
+    let param = match coli.params().find(|param| param.name() == &ParamName::Iana(IanaParam::Range)) {
+        Some(param) => param,
+        None => return Ok(None),
+    };
+    let value = param.values().next().unwrap_or("");
+    match Range::from_value(value) {
+        Range::Other(_) if !options.lenient_values => {
+            Err(Bad::Value { datatype: DataType::Range, invalid: value.to_owned() })
+        }
+        Range::Other(other) => {
+            let message = format!("RANGE must be THISANDFUTURE; {} is not interpreted", other);
+            warnings.push(Warning::new(WarningCode::InvalidValue, param.span(), message));
+            Ok(Some(Range::Other(other)))
+        }
+        range => Ok(Some(range)),
+    }
+}
+
 fn parse_data<X: FromStr>(s: &str, datatype: DataType) -> Maybe<X> {
     X::from_str(s).map_err(|_| Bad::Value { datatype, invalid: s.to_owned() })
 }
 
 pub fn parse_property(coli: &ContentLine) -> Result<Option<VEventProperty>> {
+    parse_property_with_warnings(coli, &mut Vec::new())
+}
+
+/// Like `parse_property`, recording problems that do not prevent parsing in `warnings`.
+pub fn parse_property_with_warnings(coli: &ContentLine, warnings: &mut Vec<Warning>) -> Result<Option<VEventProperty>> {
+    parse_property_with_options(coli, &ParserOptions::default(), warnings)
+}
+
+/// Like `parse_property_with_warnings`, accepting the values that `options` allows (see
+/// `ParserOptions::lenient_values`).
+pub fn parse_property_with_options(coli: &ContentLine, options: &ParserOptions, warnings: &mut Vec<Warning>)
+    -> Result<Option<VEventProperty>> {
     use VEventProperty::*;
-    let line = coli.line();
+    let span = coli.value_span();
     let iana = match coli.name() {
         Property::Iana(iana) => iana,
         Property::Extended(_) => return Ok(Some(Extended(coli.clone()))),
-        Property::End => return Ok(None),
-        Property::Begin => todo!(),
+        Property::Other(_) => return Ok(Some(Unknown)),
+        // Nesting is handled by `component::Component`.
+        Property::Begin | Property::End => return Ok(None),
     };
     Ok(Some(match iana {
-        IanaProperty::Dtstart => Dtstart(parse_when(coli).map_err(|bad| Error { bad, line } )?),
-        IanaProperty::Dtend => Dtend(parse_when(coli).map_err(|bad| Error { bad, line } )?),
+        IanaProperty::Dtstart => Dtstart(parse_when(coli).map_err(|bad| Error { bad, span } )?),
+        IanaProperty::Dtend => Dtend(parse_when(coli).map_err(|bad| Error { bad, span } )?),
         IanaProperty::Summary => Summary(coli.value().to_owned()),
-        IanaProperty::Rrule => Rrule(parse_rrule(coli).map_err(|bad| Error { bad, line } )?),
+        IanaProperty::Rrule => Rrule(parse_rrule(coli).map_err(|bad| Error { bad, span } )?),
         IanaProperty::Uid => Uid(coli.value().to_owned()),
         IanaProperty::Description => Description(coli.value().to_owned()),
         IanaProperty::Comment => Comment(coli.value().to_owned()),
-        IanaProperty::Status => Status(parse_data(coli.value(), DataType::Status).map_err(|bad| Error { bad, line } )?),
-        IanaProperty::RecurrenceId  => RecurrenceId(parse_when(coli).map_err(|bad| Error { bad, line } )?),
+        IanaProperty::Status => Status(parse_data(coli.value(), DataType::Status).map_err(|bad| Error { bad, span } )?),
+        IanaProperty::RecurrenceId  => {
+            let range = parse_range(coli, options, warnings).map_err(|bad| Error { bad, span } )?;
+            RecurrenceId(parse_when(coli).map_err(|bad| Error { bad, span } )?, range)
+        }
         IanaProperty::Location => Location(coli.value().to_owned()),
-        IanaProperty::Sequence => Sequence(parse_data(coli.value(), DataType::Int).map_err(|bad| Error { bad, line } )?),
-        IanaProperty::Transp => Transp(parse_data(coli.value(), DataType::Transp).map_err(|bad| Error { bad, line } )?),
-        IanaProperty::Dtstamp => Dtstamp(parse_utc(coli.value()).map_err(|bad| Error { bad, line } )?),
-        IanaProperty::LastModified => LastModified(parse_utc(coli.value()).map_err(|bad| Error { bad, line } )?),
-        IanaProperty::Created => Created(parse_utc(coli.value()).map_err(|bad| Error { bad, line } )?),
-        IanaProperty::Exdate => Exdate(parse_when(coli).map_err(|bad| Error { bad, line } )?),
+        IanaProperty::Sequence => Sequence(parse_data(coli.value(), DataType::Int).map_err(|bad| Error { bad, span } )?),
+        IanaProperty::Transp => Transp(parse_data(coli.value(), DataType::Transp).map_err(|bad| Error { bad, span } )?),
+        IanaProperty::Dtstamp => Dtstamp(parse_utc(coli, options, warnings).map_err(|bad| Error { bad, span } )?),
+        IanaProperty::LastModified => LastModified(parse_utc(coli, options, warnings).map_err(|bad| Error { bad, span } )?),
+        IanaProperty::Created => Created(parse_utc(coli, options, warnings).map_err(|bad| Error { bad, span } )?),
+        IanaProperty::Exdate => Exdate(parse_when_list(coli).map_err(|bad| Error { bad, span } )?),
+        IanaProperty::Exrule => Exrule(parse_rrule(coli).map_err(|bad| Error { bad, span } )?),
+        IanaProperty::Rdate => Rdate(parse_rdate(coli).map_err(|bad| Error { bad, span } )?),
+        IanaProperty::Freebusy => Freebusy(coli.fbtype(), parse_freebusy(coli, options, warnings).map_err(|bad| Error { bad, span } )?),
+        IanaProperty::Duration => Duration(parse_data(coli.value(), DataType::Duration).map_err(|bad| Error { bad, span } )?),
+        IanaProperty::Categories => Categories(parse_text_list(coli)),
+        IanaProperty::Resources => Resources(parse_text_list(coli)),
+        IanaProperty::Attendee => Attendee(parse_cal_address(coli, options, warnings).map_err(|bad| Error { bad, span } )?),
+        IanaProperty::Organizer => Organizer(parse_cal_address(coli, options, warnings).map_err(|bad| Error { bad, span } )?),
         IanaProperty::Class |
         IanaProperty::Geo |
-        IanaProperty::Organizer |
         IanaProperty::Priority |
         IanaProperty::Url |
-        IanaProperty::Duration |
         IanaProperty::Attach |
-        IanaProperty::Attendee |
-        IanaProperty::Categories |
         IanaProperty::Contact |
         IanaProperty::RequestStatus |
-        IanaProperty::RelatedTo |
-        IanaProperty::Resources |
-        IanaProperty::Rdate => {
-            debug!("VEVENT property not implemented: {}", iana.as_str());
+        IanaProperty::RelatedTo => {
+            let message = format!("{} is not implemented; kept as a content line", iana.as_str());
+            warnings.push(Warning::new(WarningCode::NotImplemented, coli.name_span(), message));
             Unknown
         }
         _ => Unknown
     }))
 }
+
+/// Properties that must not occur more than once in a VEVENT (RFC 5545 section 3.6.1). ORGANIZER must not
+/// either, but as with RRULE, any after the first are ignored with a warning.
+const AT_MOST_ONCE: &[IanaProperty] = &[
+    IanaProperty::Dtstamp,
+    IanaProperty::Uid,
+    IanaProperty::Dtstart,
+    IanaProperty::Class,
+    IanaProperty::Created,
+    IanaProperty::Description,
+    IanaProperty::Geo,
+    IanaProperty::LastModified,
+    IanaProperty::Location,
+    IanaProperty::Priority,
+    IanaProperty::Sequence,
+    IanaProperty::Status,
+    IanaProperty::Summary,
+    IanaProperty::Transp,
+    IanaProperty::Url,
+    IanaProperty::RecurrenceId,
+    IanaProperty::Dtend,
+    IanaProperty::Duration,
+];
+
+/// A VEVENT component with its properties parsed into typed values.
+#[derive(Debug)]
+pub struct VEvent {
+    dtstamp: UtcDate,
+    uid: String,
+    dtstart: Option<When>,
+    dtend: Option<When>,
+    duration: Option<Duration>,
+    rrule: Option<Rrule>,
+    exdates: Vec<When>,
+    rdates: Vec<RdateValue>,
+    exrules: Vec<Rrule>,
+    recurrence_id: Option<When>,
+    recurrence_range: Option<Range>,
+    status: Option<Status>,
+    sequence: Option<u32>,
+    summary: Option<String>,
+    description: Option<String>,
+    location: Option<String>,
+    comments: Vec<String>,
+    categories: Vec<String>,
+    resources: Vec<String>,
+    organizer: Option<CalAddress>,
+    attendees: Vec<CalAddress>,
+    transp: Option<Transp>,
+    created: Option<UtcDate>,
+    last_modified: Option<UtcDate>,
+    extended: Vec<ContentLine>,
+    other: Vec<ContentLine>,
+    alarms: Vec<Component>,
+    line: usize,
+    warnings: Vec<Warning>,
+}
+
+impl VEvent {
+    /// Assemble an event from a VEVENT component, checking the property cardinality rules of RFC 5545.
+    pub fn from_component(component: &Component) -> Result<VEvent> {
+        VEvent::from_component_with_options(component, &ParserOptions::default())
+    }
+
+    /// Like `from_component`, accepting the values that `options` allows (see
+    /// `ParserOptions::lenient_values`).
+    pub fn from_component_with_options(component: &Component, options: &ParserOptions) -> Result<VEvent> {
+        let line = component.line();
+        let span = component.span();
+        let condition = |error: String, span| Error { bad: Bad::Condition { error }, span };
+        if !component.is(IanaComponent::Vevent) {
+            return Err(condition(format!("expected VEVENT, found {}", component.name().as_str()), span));
+        }
+        for &name in AT_MOST_ONCE {
+            let mut found = component.properties().filter(|coli| coli.name() == &Property::Iana(name));
+            if let (Some(_), Some(again)) = (found.next(), found.next()) {
+                return Err(condition(format!("{} must not occur more than once", name.as_str()), again.name_span()));
+            }
+        }
+        if component.property(IanaProperty::Dtend).is_some() {
+            if let Some(duration) = component.property(IanaProperty::Duration) {
+                return Err(condition("DTEND and DURATION must not both occur".to_owned(), duration.name_span()));
+            }
+        }
+
+        let mut dtstamp = None;
+        let mut uid = None;
+        let mut event = VEvent {
+            dtstamp: UtcDate(NaiveDateTime::default()),
+            uid: String::new(),
+            dtstart: None,
+            dtend: None,
+            duration: None,
+            rrule: None,
+            exdates: Vec::new(),
+            rdates: Vec::new(),
+            exrules: Vec::new(),
+            recurrence_id: None,
+            recurrence_range: None,
+            status: None,
+            sequence: None,
+            summary: None,
+            description: None,
+            location: None,
+            comments: Vec::new(),
+            categories: Vec::new(),
+            resources: Vec::new(),
+            organizer: None,
+            attendees: Vec::new(),
+            transp: None,
+            created: None,
+            last_modified: None,
+            extended: Vec::new(),
+            other: Vec::new(),
+            alarms: component.alarms().cloned().collect(),
+            line,
+            warnings: Vec::new(),
+        };
+        for coli in component.properties() {
+            // FREEBUSY belongs in a VFREEBUSY; in a VEVENT it is kept, but not parsed, so it cannot fail the event.
+            if coli.name() == &Property::Iana(IanaProperty::Freebusy) {
+                let message = "FREEBUSY is not a VEVENT property; kept as a content line".to_owned();
+                event.warnings.push(Warning::new(WarningCode::NotImplemented, coli.name_span(), message));
+                event.other.push(coli.clone());
+                continue;
+            }
+            let prop = match parse_property_with_options(coli, options, &mut event.warnings)? {
+                Some(prop) => prop,
+                None => continue,
+            };
+            match prop {
+                VEventProperty::Dtstamp(x) => dtstamp = Some(x),
+                VEventProperty::Uid(x) => uid = Some(x),
+                VEventProperty::Dtstart(x) => event.dtstart = Some(x),
+                VEventProperty::Dtend(x) => event.dtend = Some(x),
+                VEventProperty::Duration(x) => event.duration = Some(x),
+                VEventProperty::Rrule(x) => {
+                    if event.rrule.is_some() {
+                        let message = "ignoring additional RRULE".to_owned();
+                        event.warnings.push(Warning::new(WarningCode::Duplicate, coli.name_span(), message));
+                    } else {
+                        event.rrule = Some(x);
+                    }
+                }
+                VEventProperty::Exdate(x) => event.exdates.extend(x),
+                VEventProperty::RecurrenceId(x, range) => {
+                    event.recurrence_id = Some(x);
+                    event.recurrence_range = range;
+                }
+                VEventProperty::Rdate(x) => event.rdates.extend(x),
+                VEventProperty::Exrule(x) => event.exrules.push(x),
+                VEventProperty::Status(x) => event.status = Some(x),
+                VEventProperty::Sequence(x) => event.sequence = Some(x),
+                VEventProperty::Summary(x) => event.summary = Some(x),
+                VEventProperty::Description(x) => event.description = Some(x),
+                VEventProperty::Location(x) => event.location = Some(x),
+                VEventProperty::Comment(x) => event.comments.push(x),
+                VEventProperty::Categories(x) => event.categories.extend(x),
+                VEventProperty::Resources(x) => event.resources.extend(x),
+                VEventProperty::Organizer(x) => {
+                    if event.organizer.is_some() {
+                        let message = "ignoring additional ORGANIZER".to_owned();
+                        event.warnings.push(Warning::new(WarningCode::Duplicate, coli.name_span(), message));
+                    } else {
+                        event.organizer = Some(x);
+                    }
+                }
+                VEventProperty::Attendee(x) => event.attendees.push(x),
+                VEventProperty::Transp(x) => event.transp = Some(x),
+                VEventProperty::Created(x) => event.created = Some(x),
+                VEventProperty::LastModified(x) => event.last_modified = Some(x),
+                VEventProperty::Extended(x) => event.extended.push(x),
+                // FREEBUSY is kept unparsed above.
+                VEventProperty::Unknown | VEventProperty::Freebusy(..) => event.other.push(coli.clone()),
+            }
+        }
+        event.dtstamp = dtstamp.ok_or_else(|| condition("VEVENT requires DTSTAMP".to_owned(), span))?;
+        event.uid = uid.ok_or_else(|| condition("VEVENT requires UID".to_owned(), span))?;
+        Ok(event)
+    }
+
+    pub fn dtstamp(&self) -> &UtcDate {
+        &self.dtstamp
+    }
+
+    pub fn uid(&self) -> &str {
+        &self.uid
+    }
+
+    pub fn dtstart(&self) -> Option<&When> {
+        self.dtstart.as_ref()
+    }
+
+    pub fn dtend(&self) -> Option<&When> {
+        self.dtend.as_ref()
+    }
+
+    pub fn duration(&self) -> Option<&Duration> {
+        self.duration.as_ref()
+    }
+
+    /// The end of the event (RFC 5545 section 3.6.1): DTEND, or DTSTART plus DURATION; with neither, the
+    /// day after DTSTART for an all-day event, and DTSTART otherwise. `None` without DTSTART, or if the end
+    /// is out of range. DURATION is added in wall-clock terms, as by `When::checked_add`; see
+    /// `effective_end_in`.
+    pub fn effective_end(&self) -> Option<When> {
+        self.end_with(When::checked_add)
+    }
+
+    /// The end of the event, as `effective_end`, with DURATION added as by `When::checked_add_in`. `None`
+    /// also if DTSTART cannot be resolved with `zones`.
+    pub fn effective_end_in(&self, zones: &Timezones) -> Option<When> {
+        self.end_with(|start, duration| start.checked_add_in(duration, zones))
+    }
+
+    fn end_with(&self, add: impl Fn(&When, &Duration) -> Option<When>) -> Option<When> {
+        let start = self.dtstart.as_ref()?;
+        match (&self.dtend, &self.duration) {
+            (Some(end), _) => Some(end.clone()),
+            (None, Some(duration)) => add(start, duration),
+            (None, None) if matches!(start, When::Date(_)) => start.checked_add(&Duration::days(1)),
+            (None, None) => Some(start.clone()),
+        }
+    }
+
+    pub fn rrule(&self) -> Option<&Rrule> {
+        self.rrule.as_ref()
+    }
+
+    /// All EXDATE values, across every EXDATE line.
+    pub fn exdates(&self) -> &[When] {
+        &self.exdates
+    }
+
+    /// All RDATE values, across every RDATE line.
+    pub fn rdates(&self) -> &[RdateValue] {
+        &self.rdates
+    }
+
+    /// EXRULEs (deprecated by RFC 5545, but still produced by some software).
+    pub fn exrules(&self) -> &[Rrule] {
+        &self.exrules
+    }
+
+    pub fn recurrence_id(&self) -> Option<&When> {
+        self.recurrence_id.as_ref()
+    }
+
+    /// The RANGE of the RECURRENCE-ID, if it applies to more than one instance.
+    pub fn recurrence_range(&self) -> Option<&Range> {
+        self.recurrence_range.as_ref()
+    }
+
+    pub fn status(&self) -> Option<Status> {
+        self.status
+    }
+
+    /// The revision sequence number; 0 if not specified.
+    pub fn sequence(&self) -> u32 {
+        self.sequence.unwrap_or(0)
+    }
+
+    pub fn summary(&self) -> Option<&str> {
+        self.summary.as_deref()
+    }
+
+    pub fn description(&self) -> Option<&str> {
+        self.description.as_deref()
+    }
+
+    pub fn location(&self) -> Option<&str> {
+        self.location.as_deref()
+    }
+
+    pub fn comments(&self) -> impl Iterator<Item=&str> {
+        self.comments.iter().map(|s| s.as_str())
+    }
+
+    /// All CATEGORIES, across every CATEGORIES line.
+    pub fn categories(&self) -> impl Iterator<Item=&str> {
+        self.categories.iter().map(|s| s.as_str())
+    }
+
+    /// All RESOURCES, across every RESOURCES line.
+    pub fn resources(&self) -> impl Iterator<Item=&str> {
+        self.resources.iter().map(|s| s.as_str())
+    }
+
+    pub fn organizer(&self) -> Option<&CalAddress> {
+        self.organizer.as_ref()
+    }
+
+    pub fn attendees(&self) -> &[CalAddress] {
+        &self.attendees
+    }
+
+    /// The attendee with the given mailto: address, compared case-insensitively.
+    pub fn attendee(&self, email: &str) -> Option<&CalAddress> {
+        self.attendees.iter().find(|a| a.email().is_some_and(|e| e.eq_ignore_ascii_case(email)))
+    }
+
+    pub fn transp(&self) -> Option<Transp> {
+        self.transp
+    }
+
+    pub fn created(&self) -> Option<&UtcDate> {
+        self.created.as_ref()
+    }
+
+    pub fn last_modified(&self) -> Option<&UtcDate> {
+        self.last_modified.as_ref()
+    }
+
+    /// X- properties.
+    pub fn extended(&self) -> impl Iterator<Item=&ContentLine> {
+        self.extended.iter()
+    }
+
+    /// IANA properties that are not (yet) parsed into typed values.
+    pub fn other(&self) -> impl Iterator<Item=&ContentLine> {
+        self.other.iter()
+    }
+
+    pub fn alarms(&self) -> impl Iterator<Item=&Component> {
+        self.alarms.iter()
+    }
+
+    /// Line number of the event's BEGIN.
+    pub fn line(&self) -> usize {
+        self.line
+    }
+
+    /// Problems found in the event's properties that did not prevent parsing.
+    pub fn warnings(&self) -> &[Warning] {
+        &self.warnings
+    }
+}
+
+fn format_date(d: NaiveDate) -> String {
+    d.format("%Y%m%d").to_string()
+}
+
+fn format_datetime(dt: NaiveDateTime, utc: bool) -> String {
+    let mut s = dt.format("%Y%m%dT%H%M%S").to_string();
+    if utc {
+        s.push('Z');
+    }
+    s
+}
+
+impl std::fmt::Display for UtcDate {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        f.write_str(&format_datetime(self.0, true))
+    }
+}
+
+/// Formats the value only; a TZID must be written as a parameter.
+impl std::fmt::Display for DateTime {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        f.write_str(&format_datetime(self.naive(), matches!(self, DateTime::Utc(_))))
+    }
+}
+
+/// Formats the value only; a TZID or VALUE=DATE must be written as a parameter.
+impl std::fmt::Display for When {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        match self {
+            When::Date(d) => f.write_str(&format_date(*d)),
+            When::DateTime(dt) => write!(f, "{}", dt),
+        }
+    }
+}
+
+impl When {
+    /// The VALUE and TZID parameters needed to write this value.
+    fn params(&self) -> Vec<Param> {
+        match self {
+            When::Date(_) => vec![Param::new(ParamName::Iana(IanaParam::Value), vec!["DATE".to_owned()])],
+            When::DateTime(DateTime::Local(_, tzid)) =>
+                vec![Param::new(ParamName::Iana(IanaParam::Tzid), vec![tzid.clone()])],
+            When::DateTime(_) => Vec::new(),
+        }
+    }
+}
+
+/// Formats the value only; a TZID must be written as a parameter.
+impl std::fmt::Display for Period {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        match &self.end {
+            PeriodEnd::End(end) => write!(f, "{}/{}", self.start, end),
+            PeriodEnd::Duration(d) => write!(f, "{}/{}", self.start, d),
+        }
+    }
+}
+
+/// Formats the value only; a TZID or VALUE must be written as a parameter.
+impl std::fmt::Display for RdateValue {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        match self {
+            RdateValue::When(when) => write!(f, "{}", when),
+            RdateValue::Period(period) => write!(f, "{}", period),
+        }
+    }
+}
+
+impl RdateValue {
+    /// The VALUE and TZID parameters needed to write this value.
+    fn params(&self) -> Vec<Param> {
+        match self {
+            RdateValue::When(when) => when.params(),
+            RdateValue::Period(period) => {
+                let mut params = vec![Param::new(ParamName::Iana(IanaParam::Value), vec!["PERIOD".to_owned()])];
+                params.extend(When::DateTime(period.start.clone()).params());
+                params
+            }
+        }
+    }
+}
+
+impl CalAddress {
+    /// The parameters, in the order of RFC 5545, then any others.
+    fn params(&self) -> Vec<Param> {
+        let mut params = Vec::new();
+        let mut add = |name, values: Vec<String>| {
+            if !values.is_empty() {
+                params.push(Param::new(ParamName::Iana(name), values));
+            }
+        };
+        let one = |value: Option<&str>| value.map(|s| s.to_owned()).into_iter().collect::<Vec<_>>();
+        add(IanaParam::Cutype, one(self.cutype.as_ref().map(Cutype::as_str)));
+        add(IanaParam::Member, self.member.clone());
+        add(IanaParam::Role, one(self.role.as_ref().map(Role::as_str)));
+        add(IanaParam::Partstat, one(self.partstat.as_ref().map(Partstat::as_str)));
+        add(IanaParam::Rsvp, one(self.rsvp.map(|b| if b { "TRUE" } else { "FALSE" })));
+        add(IanaParam::DelegatedTo, self.delegated_to.clone());
+        add(IanaParam::DelegatedFrom, self.delegated_from.clone());
+        add(IanaParam::SentBy, one(self.sent_by.as_deref()));
+        add(IanaParam::Cn, one(self.cn.as_deref()));
+        add(IanaParam::Dir, one(self.dir.as_deref()));
+        add(IanaParam::Language, one(self.language.as_deref()));
+        params.extend(self.other_params.iter().cloned());
+        params
+    }
+}
+
+fn join<X: std::fmt::Display>(xs: &[X]) -> String {
+    xs.iter().map(|x| x.to_string()).collect::<Vec<_>>().join(",")
+}
+
+impl std::fmt::Display for Rrule {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        fn list<X: std::fmt::Display>(f: &mut std::fmt::Formatter, name: &str, xs: &Option<Vec<X>>) -> std::fmt::Result {
+            if let Some(xs) = xs {
+                write!(f, ";{}=", name)?;
+                for (i, x) in xs.iter().enumerate() {
+                    if i > 0 {
+                        f.write_str(",")?;
+                    }
+                    write!(f, "{}", x)?;
+                }
+            }
+            Ok(())
+        }
+        write!(f, "FREQ={}", self.freq.as_str())?;
+        match &self.stop {
+            Some(Stop::Until(Until::Date(d))) => write!(f, ";UNTIL={}", format_date(*d))?,
+            Some(Stop::Until(Until::DateTime(dt))) => write!(f, ";UNTIL={}", dt)?,
+            Some(Stop::Count(n)) => write!(f, ";COUNT={}", n)?,
+            None => (),
+        }
+        if let Some(interval) = self.interval {
+            write!(f, ";INTERVAL={}", interval)?;
+        }
+        list(f, "BYSECOND", &self.bysecond)?;
+        list(f, "BYMINUTE", &self.byminute)?;
+        list(f, "BYHOUR", &self.byhour)?;
+        list(f, "BYDAY", &self.byday)?;
+        list(f, "BYMONTHDAY", &self.bymonthday)?;
+        list(f, "BYYEARDAY", &self.byyearday)?;
+        list(f, "BYWEEKNO", &self.byweekno)?;
+        list(f, "BYMONTH", &self.bymonth)?;
+        list(f, "BYSETPOS", &self.bysetpos)?;
+        if let Some(wkst) = self.wkst {
+            write!(f, ";WKST={}", wkst.as_str())?;
+        }
+        Ok(())
+    }
+}
+
+impl std::fmt::Display for WeekdayNum {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        if let Some(num) = self.num {
+            write!(f, "{}", num)?;
+        }
+        f.write_str(self.wday.as_str())
+    }
+}
+
+impl VEventProperty {
+    /// Convert the property back to a content line, e.g. for writing. Returns `None` for `Unknown`.
+    pub fn to_content_line(&self) -> Option<ContentLine> {
+        use VEventProperty::*;
+        let text = |name, value: &String| (name, Vec::new(), value.clone());
+        // A list is made as the lexer would read it: the value is the escaped items, unescaped, and it is written
+        // as the escaped items.
+        let list = |name, items: &[String]| {
+            let mut raw = Vec::new();
+            for (i, item) in items.iter().enumerate() {
+                if i > 0 {
+                    raw.push(b',');
+                }
+                crate::writer::escape_text(&mut raw, item, b"");
+            }
+            let mut coli = ContentLine::new(Property::Iana(name), Vec::new(), String::from_utf8_lossy(&raw).into_owned());
+            coli.value_set = false;
+            if let Some(value) = crate::unescape_text(&coli.value) {
+                coli.raw_value = Some(std::mem::replace(&mut coli.value, value));
+            }
+            Some(coli)
+        };
+        let when = |name, value: &When| (name, value.params(), value.to_string());
+        let (name, params, value) = match self {
+            Unknown => return None,
+            Extended(coli) => return Some(coli.clone()),
+            Categories(x) => return list(IanaProperty::Categories, x),
+            Resources(x) => return list(IanaProperty::Resources, x),
+            Dtstart(x) => when(IanaProperty::Dtstart, x),
+            Dtend(x) => when(IanaProperty::Dtend, x),
+            Exdate(x) => (IanaProperty::Exdate, x.first().map(When::params).unwrap_or_default(), join(x)),
+            Rdate(x) => (IanaProperty::Rdate, x.first().map(RdateValue::params).unwrap_or_default(), join(x)),
+            Freebusy(fbtype, x) => {
+                let params = fbtype.iter()
+                    .map(|fbtype| Param::new(ParamName::Iana(IanaParam::Fbtype), vec![fbtype.as_str().to_owned()]))
+                    .collect();
+                (IanaProperty::Freebusy, params, join(x))
+            }
+            Attendee(x) => (IanaProperty::Attendee, x.params(), x.uri.clone()),
+            Organizer(x) => (IanaProperty::Organizer, x.params(), x.uri.clone()),
+            RecurrenceId(x, range) => {
+                let (name, mut params, value) = when(IanaProperty::RecurrenceId, x);
+                if let Some(range) = range {
+                    params.push(Param::new(ParamName::Iana(IanaParam::Range), vec![range.as_str().to_owned()]));
+                }
+                (name, params, value)
+            }
+            Summary(x) => text(IanaProperty::Summary, x),
+            Uid(x) => text(IanaProperty::Uid, x),
+            Description(x) => text(IanaProperty::Description, x),
+            Comment(x) => text(IanaProperty::Comment, x),
+            Location(x) => text(IanaProperty::Location, x),
+            Rrule(x) => (IanaProperty::Rrule, Vec::new(), x.to_string()),
+            Exrule(x) => (IanaProperty::Exrule, Vec::new(), x.to_string()),
+            Status(x) => (IanaProperty::Status, Vec::new(), x.as_str().to_owned()),
+            Transp(x) => (IanaProperty::Transp, Vec::new(), x.as_str().to_owned()),
+            Sequence(x) => (IanaProperty::Sequence, Vec::new(), x.to_string()),
+            Duration(x) => (IanaProperty::Duration, Vec::new(), x.to_string()),
+            Dtstamp(x) => (IanaProperty::Dtstamp, Vec::new(), x.to_string()),
+            Created(x) => (IanaProperty::Created, Vec::new(), x.to_string()),
+            LastModified(x) => (IanaProperty::LastModified, Vec::new(), x.to_string()),
+        };
+        Some(ContentLine::new(Property::Iana(name), params, value))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::Lexer;
+    use crate::param::{IanaFbtype, IanaRange};
+
+    fn component(properties: &str) -> Component {
+        let input = format!("BEGIN:VEVENT\r\n{}END:VEVENT\r\n", properties);
+        Component::parse(&mut Lexer::new(input.as_bytes())).unwrap().unwrap()
+    }
+
+    fn event(properties: &str) -> Result<VEvent> {
+        VEvent::from_component(&component(properties))
+    }
+
+    fn datetime(s: &str) -> NaiveDateTime {
+        NaiveDateTime::parse_from_str(s, "%Y%m%dT%H%M%S").unwrap()
+    }
+
+    const REQUIRED: &str = "UID:abc\r\nDTSTAMP:20240101T120000Z\r\n";
+
+    #[test]
+    fn typed_properties() {
+        let ev = event(&format!("{}DTSTART;TZID=Europe/Berlin:20240102T090000\r\nDTEND;VALUE=DATE:20240103\r\n\
+                                 SUMMARY:Meeting\r\nSTATUS:CONFIRMED\r\nSEQUENCE:3\r\nTRANSP:OPAQUE\r\n\
+                                 COMMENT:one\r\nCOMMENT:two\r\nX-FOO:bar\r\n", REQUIRED)).unwrap();
+        assert_eq!(ev.uid(), "abc");
+        assert_eq!(ev.dtstamp().naive_utc(), datetime("20240101T120000"));
+        assert_eq!(ev.dtstart(), Some(&When::DateTime(DateTime::Local(datetime("20240102T090000"), "Europe/Berlin".to_owned()))));
+        assert_eq!(ev.dtend(), Some(&When::Date(NaiveDate::from_ymd_opt(2024, 1, 3).unwrap())));
+        assert_eq!(ev.summary(), Some("Meeting"));
+        assert_eq!(ev.status(), Some(Status::Confirmed));
+        assert_eq!(ev.sequence(), 3);
+        assert_eq!(ev.transp(), Some(Transp::Opaque));
+        assert_eq!(ev.comments().collect::<Vec<_>>(), ["one", "two"]);
+        assert_eq!(ev.extended().count(), 1);
+        assert_eq!(ev.line(), 1);
+    }
+
+    #[test]
+    fn required_properties() {
+        assert_eq!(event("UID:abc\r\n").unwrap_err().kind(), ErrorKind::Constraint);
+        assert_eq!(event("DTSTAMP:20240101T120000Z\r\n").unwrap_err().kind(), ErrorKind::Constraint);
+    }
+
+    #[test]
+    fn cardinality() {
+        let e = event(&format!("{}SUMMARY:a\r\nSUMMARY:b\r\n", REQUIRED)).unwrap_err();
+        assert_eq!(e.kind(), ErrorKind::Constraint);
+        assert_eq!(e.line(), 5);
+        let e = event(&format!("{}DTSTART:20240101T100000Z\r\nDTEND:20240101T110000Z\r\nDURATION:PT1H\r\n", REQUIRED));
+        assert_eq!(e.unwrap_err().kind(), ErrorKind::Constraint);
+    }
+
+    #[test]
+    fn bad_values() {
+        let e = event(&format!("{}DTSTART:20241301T100000\r\n", REQUIRED)).unwrap_err();
+        assert_eq!(e.kind(), ErrorKind::BadValue(DataType::DateTime));
+        let e = event(&format!("{}DTSTART;TZID=Europe/Berlin:20240101T100000Z\r\n", REQUIRED)).unwrap_err();
+        assert_eq!(e.kind(), ErrorKind::Constraint);
+    }
+
+    #[test]
+    fn not_a_vevent() {
+        let todo = Component::parse(&mut Lexer::new(&b"BEGIN:VTODO\r\nUID:1\r\nEND:VTODO\r\n"[..])).unwrap().unwrap();
+        assert_eq!(VEvent::from_component(&todo).unwrap_err().kind(), ErrorKind::Constraint);
+    }
+
+    #[test]
+    fn warnings() {
+        let properties = "UID:abc\r\nDTSTAMP:20240101T120000\r\nCLASS:PUBLIC\r\nRRULE:FREQ=DAILY\r\nRRULE:FREQ=WEEKLY\r\n";
+        let lenient = ParserOptions::default().lenient_values(true);
+        let ev = VEvent::from_component_with_options(&component(properties), &lenient).unwrap();
+        let warnings: Vec<_> = ev.warnings().iter().map(|w| (w.code(), w.line())).collect();
+        assert_eq!(warnings, [(WarningCode::NotUtc, 3), (WarningCode::NotImplemented, 4), (WarningCode::Duplicate, 6)]);
+        assert_eq!(ev.dtstamp().naive_utc(), datetime("20240101T120000"));
+        // By default, a DTSTAMP that is not UTC is an error.
+        assert_eq!(event(properties).unwrap_err().kind(), ErrorKind::BadValue(DataType::UtcDate));
+    }
+
+    #[test]
+    fn durations() {
+        for s in ["P1W", "P15DT5H0M20S", "-PT15M", "PT1H", "P2D", "PT0S", "-P3DT4H", "PT10S", "PT2H30M"] {
+            let d: Duration = s.parse().unwrap();
+            assert_eq!(d.to_string(), s);
+        }
+        let d: Duration = "+P1W2D".parse().unwrap();
+        assert_eq!((d.to_string(), d.nominal_days()), ("P9D".to_owned(), 9));
+        assert_eq!("P0D".parse::<Duration>().unwrap().to_string(), "PT0S");
+        assert_eq!("-PT1H1S".parse::<Duration>().unwrap().exact(), -chrono::Duration::seconds(3601));
+        for s in ["", "P", "PT", "P1H", "1D", "PT1D", "P1DT", "P1S", "P1D1W", "PT1S1M", "P-1D", "p1d"] {
+            assert!(s.parse::<Duration>().is_err(), "{:?}", s);
+        }
+        assert_eq!(-(-Duration::hms(1, 2, 3)), Duration::hms(1, 2, 3));
+    }
+
+    /// Europe/Berlin since 1996.
+    const BERLIN: &str = "BEGIN:VCALENDAR\r\nBEGIN:VTIMEZONE\r\nTZID:Europe/Berlin\r\n\
+                          BEGIN:STANDARD\r\nDTSTART:19961027T030000\r\nTZOFFSETFROM:+0200\r\nTZOFFSETTO:+0100\r\n\
+                          RRULE:FREQ=YEARLY;BYMONTH=10;BYDAY=-1SU\r\nEND:STANDARD\r\n\
+                          BEGIN:DAYLIGHT\r\nDTSTART:19810329T020000\r\nTZOFFSETFROM:+0100\r\nTZOFFSETTO:+0200\r\n\
+                          RRULE:FREQ=YEARLY;BYMONTH=3;BYDAY=-1SU\r\nEND:DAYLIGHT\r\nEND:VTIMEZONE\r\nEND:VCALENDAR\r\n";
+
+    fn berlin() -> Timezones {
+        let calendar = Component::parse(&mut Lexer::new(BERLIN.as_bytes())).unwrap().unwrap();
+        Timezones::from_calendar(&calendar).unwrap()
+    }
+
+    #[test]
+    fn adding_durations() {
+        let local = |s| When::DateTime(DateTime::Local(datetime(s), "Europe/Berlin".to_owned()));
+        let hour: Duration = "PT1H".parse().unwrap();
+        let zones = berlin();
+        // 01:30 CET is 00:30 UTC; an hour later is 01:30 UTC, which is 03:30 CEST. The wall clock alone
+        // gives 02:30, which does not exist that day.
+        let start = local("20240331T013000");
+        assert_eq!(start.checked_add(&hour), Some(local("20240331T023000")));
+        assert_eq!(start.checked_add_in(&hour, &zones), Some(local("20240331T033000")));
+        // Back from 03:30 CEST is 01:30 CET.
+        assert_eq!(local("20240331T033000").checked_add_in(&-hour, &zones), Some(local("20240331T013000")));
+        // A day is nominal: the same wall-clock time, although only 23 hours pass.
+        let day = Duration::days(1);
+        assert_eq!(local("20240330T120000").checked_add_in(&day, &zones), Some(local("20240331T120000")));
+        let both: Duration = "P1DT1H".parse().unwrap();
+        assert_eq!(local("20240330T013000").checked_add_in(&both, &zones), Some(local("20240331T033000")));
+
+        // UTC times and DATEs need no timezone; other times do.
+        let none = Timezones::new();
+        let utc = When::DateTime(DateTime::Utc(datetime("20240331T003000")));
+        assert_eq!(utc.checked_add_in(&hour, &none), Some(When::DateTime(DateTime::Utc(datetime("20240331T013000")))));
+        let date = When::Date(NaiveDate::from_ymd_opt(2024, 2, 28).unwrap());
+        assert_eq!(date.checked_add_in(&Duration::weeks(1), &none), Some(When::Date(NaiveDate::from_ymd_opt(2024, 3, 6).unwrap())));
+        assert_eq!(date.clone() + hour, When::DateTime(DateTime::Floating(datetime("20240228T010000"))));
+        assert_eq!(date - day, When::Date(NaiveDate::from_ymd_opt(2024, 2, 27).unwrap()));
+        let unknown = When::DateTime(DateTime::Local(datetime("20240331T013000"), "Nowhere".to_owned()));
+        assert_eq!(unknown.checked_add_in(&hour, &none), None);
+        assert_eq!(When::DateTime(DateTime::Floating(datetime("20240331T013000"))).checked_add_in(&hour, &none), None);
+        assert_eq!(utc.checked_add(&Duration::weeks(u32::MAX)), None);
+    }
+
+    #[test]
+    fn effective_end() {
+        let end = |properties: &str| event(&format!("{}{}", REQUIRED, properties)).unwrap().effective_end();
+        let utc = |s| When::DateTime(DateTime::Utc(datetime(s)));
+        assert_eq!(end("DTSTART:20240101T100000Z\r\nDTEND:20240101T120000Z\r\n"), Some(utc("20240101T120000")));
+        assert_eq!(end("DTSTART:20240101T100000Z\r\nDURATION:PT90M\r\n"), Some(utc("20240101T113000")));
+        assert_eq!(end("DTSTART:20240101T100000Z\r\n"), Some(utc("20240101T100000")));
+        assert_eq!(end("DTSTART;VALUE=DATE:20240101\r\n"), Some(When::Date(NaiveDate::from_ymd_opt(2024, 1, 2).unwrap())));
+        assert_eq!(end(""), None);
+
+        let ev = event(&format!("{}DTSTART;TZID=Europe/Berlin:20240331T013000\r\nDURATION:PT1H\r\n", REQUIRED)).unwrap();
+        let local = When::DateTime(DateTime::Local(datetime("20240331T033000"), "Europe/Berlin".to_owned()));
+        assert_eq!(ev.effective_end_in(&berlin()), Some(local));
+        let ev = event(&format!("{}DTSTART;TZID=Nowhere:20240331T013000\r\nDURATION:PT1H\r\n", REQUIRED)).unwrap();
+        assert_eq!(ev.effective_end_in(&berlin()), None);
+    }
+
+    #[test]
+    fn periods() {
+        let utc = |s| DateTime::Utc(datetime(s));
+        let period = parse_period("19970101T180000Z/PT5H30M", None).unwrap();
+        assert_eq!(period.end_time(), Some(utc("19970101T233000")));
+        assert_eq!(period.to_string(), "19970101T180000Z/PT5H30M");
+        let period = parse_period("19970101T180000/19970102T070000", Some("Europe/Berlin")).unwrap();
+        assert_eq!(period.start(), &DateTime::Local(datetime("19970101T180000"), "Europe/Berlin".to_owned()));
+        // The end must not be before the start.
+        for s in ["19970101T180000Z/19970101T170000Z", "19970101T180000/19970101T170000", "19970101T180000Z/-PT1H"] {
+            assert!(matches!(parse_period(s, None), Err(Bad::Value { datatype: DataType::Period, .. })), "{}", s);
+        }
+        assert!(parse_period("19970101T180000Z/19970101T180000Z", None).is_ok());
+        assert!(parse_period("19970101T180000Z", None).is_err());
+
+        let ev = event(&format!("{}DTSTART:19970101T100000Z\r\nRDATE;VALUE=PERIOD:19970101T180000Z/PT1H,19970102T180000Z/19970102T190000Z\r\n", REQUIRED)).unwrap();
+        assert_eq!(ev.rdates().iter().map(|r| r.period().unwrap().end_time().unwrap()).collect::<Vec<_>>(),
+                   [utc("19970101T190000"), utc("19970102T190000")]);
+        let e = event(&format!("{}RDATE;VALUE=PERIOD:19970101T180000Z/19970101T170000Z\r\n", REQUIRED)).unwrap_err();
+        assert_eq!(e.kind(), ErrorKind::BadValue(DataType::Period));
+        // Values that are not ASCII are invalid, not a panic.
+        for value in ["20240101T000000Z/20240101T12345é", "20240101T00000é/PT1H", "20240101T000000Z/PTé1H", "20240101T000000Z/P1é"] {
+            let e = event(&format!("{}RDATE;VALUE=PERIOD:{}\r\n", REQUIRED, value)).unwrap_err();
+            assert_eq!(e.kind(), ErrorKind::BadValue(DataType::Period), "{}", value);
+        }
+    }
+
+    #[test]
+    fn freebusy() {
+        let line = |value: &str| {
+            let input = format!("FREEBUSY;FBTYPE=BUSY:{}\r\n", value);
+            Lexer::new(input.as_bytes()).content_lines().next().unwrap().unwrap()
+        };
+        let parse = |value, options: &ParserOptions, warnings: &mut Vec<Warning>| {
+            parse_property_with_options(&line(value), options, warnings).map(Option::unwrap)
+        };
+        let mut warnings = Vec::new();
+        let busy = parse("19970308T160000Z/PT3H,19970308T200000Z/19970308T210000Z", &ParserOptions::default(), &mut warnings).unwrap();
+        assert!(matches!(&busy, VEventProperty::Freebusy(Some(Fbtype::Iana(IanaFbtype::Busy)), periods) if periods.len() == 2));
+        assert!(warnings.is_empty());
+        // FBTYPE is written back.
+        let coli = busy.to_content_line().unwrap();
+        assert_eq!(coli.value_of(IanaParam::Fbtype), Some("BUSY"));
+        assert_eq!(coli.value(), "19970308T160000Z/PT3H,19970308T200000Z/19970308T210000Z");
+        // Periods must be UTC, or are taken as UTC with lenient values.
+        let e = parse("19970308T160000/PT3H", &ParserOptions::default(), &mut warnings).unwrap_err();
+        assert_eq!(e.kind(), ErrorKind::BadValue(DataType::Period));
+        assert!(parse("19970308T160000Z/19970308T170000", &ParserOptions::default(), &mut warnings).is_err());
+        let lenient = ParserOptions::default().lenient_values(true);
+        let busy = parse("19970308T160000/PT3H", &lenient, &mut warnings).unwrap();
+        let expected = Period::new(DateTime::Utc(datetime("19970308T160000")), PeriodEnd::Duration("PT3H".parse().unwrap()));
+        assert!(matches!(busy, VEventProperty::Freebusy(_, periods) if periods == [expected]));
+        assert_eq!(warnings.iter().map(Warning::code).collect::<Vec<_>>(), [WarningCode::NotUtc]);
+
+        // In a VEVENT, FREEBUSY is kept without being parsed, even if it is invalid.
+        let ev = event(&format!("{}FREEBUSY:19970308T160000/garbage\r\n", REQUIRED)).unwrap();
+        assert_eq!(ev.other().count(), 1);
+        assert_eq!(ev.warnings().iter().map(|w| (w.code(), w.line())).collect::<Vec<_>>(), [(WarningCode::NotImplemented, 4)]);
+    }
+
+    #[test]
+    fn date_lists() {
+        let ev = event(&format!("{}DTSTART:20240101T100000Z\r\nEXDATE:20240101T100000Z,20240108T100000Z\r\n\
+                                 EXDATE;TZID=Europe/Berlin:20240115T110000,20240122T110000\r\nEXDATE;VALUE=DATE:20240129\r\n\
+                                 RDATE:20240102T100000Z\r\nRDATE;VALUE=DATE:20240103,20240104\r\n", REQUIRED)).unwrap();
+        let utc = |s| When::DateTime(DateTime::Utc(datetime(s)));
+        let local = |s| When::DateTime(DateTime::Local(datetime(s), "Europe/Berlin".to_owned()));
+        let date = |d| When::Date(NaiveDate::from_ymd_opt(2024, 1, d).unwrap());
+        assert_eq!(ev.exdates(), [utc("20240101T100000"), utc("20240108T100000"), local("20240115T110000"),
+                                  local("20240122T110000"), date(29)]);
+        assert_eq!(ev.rdates().iter().map(RdateValue::start).collect::<Vec<_>>(), [utc("20240102T100000"), date(3), date(4)]);
+
+        // Every value must be of the type the parameters give.
+        let e = event(&format!("{}EXDATE;VALUE=DATE:20240101,20240108T100000Z\r\n", REQUIRED)).unwrap_err();
+        assert_eq!((e.kind(), e.line()), (ErrorKind::BadValue(DataType::Date), 4));
+        let e = event(&format!("{}EXDATE;TZID=Europe/Berlin:20240101T100000,20240108T100000Z\r\n", REQUIRED)).unwrap_err();
+        assert_eq!(e.kind(), ErrorKind::Constraint);
+        // Values that are not ASCII are invalid, wherever the characters fall.
+        for value in ["20240101T12345é", "20240101Té12345", "202é101", "20240é1", "20240101T+12345Z", "+2024010"] {
+            let e = event(&format!("{}EXDATE:20240101T100000Z,{}\r\n", REQUIRED, value)).unwrap_err();
+            assert!(matches!(e.kind(), ErrorKind::BadValue(_)), "{}", value);
+            let e = event(&format!("{}EXDATE;VALUE=DATE:{}\r\n", REQUIRED, value)).unwrap_err();
+            assert_eq!(e.kind(), ErrorKind::BadValue(DataType::Date), "{}", value);
+        }
+
+        // A list is written with the shared parameters.
+        let coli = VEventProperty::Exdate(vec![local("20240115T110000"), local("20240122T110000")]).to_content_line().unwrap();
+        let mut writer = crate::writer::Writer::new(Vec::new());
+        writer.write_content_line(&coli).unwrap();
+        assert_eq!(writer.finish(), b"EXDATE;TZID=Europe/Berlin:20240115T110000,20240122T110000\r\n");
+    }
+
+    #[test]
+    fn cal_addresses() {
+        let ev = event(&format!("{}ORGANIZER;CN=\"Doe, Jane\";SENT-BY=\"mailto:b@x\":mailto:jane@x\r\n\
+                                 ATTENDEE;CUTYPE=GROUP;ROLE=CHAIR;PARTSTAT=ACCEPTED;RSVP=TRUE;MEMBER=\"mailto:g@x\",\"mailto:h@x\";\
+                                 DELEGATED-TO=\"mailto:d@x\";X-FOO=bar:MAILTO:joe@x\r\nATTENDEE;PARTSTAT=X-MAYBE:urn:uuid:1\r\n", REQUIRED)).unwrap();
+        let organizer = ev.organizer().unwrap();
+        assert_eq!((organizer.cn(), organizer.email(), organizer.sent_by()), (Some("Doe, Jane"), Some("jane@x"), Some("mailto:b@x")));
+        assert_eq!(organizer.role(), Role::Iana(IanaRole::ReqParticipant));
+        let attendees = ev.attendees();
+        assert_eq!(attendees[0].email(), Some("joe@x"));
+        assert_eq!(attendees[0].cutype(), Cutype::Iana(IanaCutype::Group));
+        assert_eq!(attendees[0].partstat(), Partstat::Iana(IanaPartstat::Accepted));
+        assert!(attendees[0].rsvp());
+        assert_eq!(attendees[0].member().collect::<Vec<_>>(), ["mailto:g@x", "mailto:h@x"]);
+        assert_eq!(attendees[0].delegated_to().collect::<Vec<_>>(), ["mailto:d@x"]);
+        assert_eq!((attendees[1].email(), attendees[1].rsvp()), (None, false));
+        assert_eq!(attendees[1].partstat(), Partstat::Other("X-MAYBE".to_owned()));
+
+        // Parameters are written back, including those not interpreted.
+        let coli = VEventProperty::Attendee(attendees[0].clone()).to_content_line().unwrap();
+        assert_eq!(coli.value_of(IanaParam::Rsvp), Some("TRUE"));
+        assert!(coli.params().any(|p| p.name().as_str() == "X-FOO"));
+    }
+
+    #[test]
+    fn cal_address_leniencies() {
+        let properties = format!("{}ORGANIZER:mailto:a@x\r\nATTENDEE;RSVP=yes:mailto:b@x\r\nORGANIZER:mailto:c@x\r\n", REQUIRED);
+        let e = event(&properties).unwrap_err();
+        assert_eq!((e.kind(), e.line()), (ErrorKind::BadValue(DataType::Boolean), 5));
+
+        // With lenient values, an RSVP that is not a BOOLEAN is ignored, but kept for writing.
+        let lenient = ParserOptions::default().lenient_values(true);
+        let ev = VEvent::from_component_with_options(&component(&properties), &lenient).unwrap();
+        let attendee = &ev.attendees()[0];
+        assert!(!attendee.rsvp());
+        assert_eq!(VEventProperty::Attendee(attendee.clone()).to_content_line().unwrap().value_of(IanaParam::Rsvp), Some("yes"));
+        // Only the first ORGANIZER is used.
+        assert_eq!(ev.organizer().unwrap().email(), Some("a@x"));
+        let warnings: Vec<_> = ev.warnings().iter().map(|w| (w.code(), w.line())).collect();
+        assert_eq!(warnings, [(WarningCode::InvalidValue, 5), (WarningCode::Duplicate, 6)]);
+    }
+
+    #[test]
+    fn recurrence_ranges() {
+        let ev = event(&format!("{}RECURRENCE-ID;RANGE=thisandfuture:20240101T090000Z\r\n", REQUIRED)).unwrap();
+        assert_eq!(ev.recurrence_range(), Some(&Range::Iana(IanaRange::ThisAndFuture)));
+        assert!(ev.warnings().is_empty());
+
+        // RFC 5545 removed THISANDPRIOR.
+        let properties = format!("{}RECURRENCE-ID;RANGE=THISANDPRIOR:20240101T090000Z\r\n", REQUIRED);
+        let e = event(&properties).unwrap_err();
+        assert_eq!((e.kind(), e.line()), (ErrorKind::BadValue(DataType::Range), 4));
+        assert_eq!(DataType::Range.to_string(), "RANGE");
+
+        let lenient = ParserOptions::default().lenient_values(true);
+        let ev = VEvent::from_component_with_options(&component(&properties), &lenient).unwrap();
+        assert_eq!(ev.recurrence_range(), Some(&Range::Other("THISANDPRIOR".to_owned())));
+        let warnings: Vec<_> = ev.warnings().iter().map(|w| (w.code(), w.line())).collect();
+        assert_eq!(warnings, [(WarningCode::InvalidValue, 4)]);
+    }
+
+    #[test]
+    fn text_lists() {
+        let ev = event(&format!("{}CATEGORIES:a\\,b,c\\nd\r\nCATEGORIES:e\r\nCATEGORIES:\r\nRESOURCES:x\\;y,,z\r\n", REQUIRED)).unwrap();
+        assert_eq!(ev.categories().collect::<Vec<_>>(), ["a,b", "c\nd", "e"]);
+        assert_eq!(ev.resources().collect::<Vec<_>>(), ["x;y", "", "z"]);
+
+        let categories = vec!["a,b".to_owned(), "c\nd".to_owned(), "e\\".to_owned()];
+        let coli = VEventProperty::Categories(categories.clone()).to_content_line().unwrap();
+        assert_eq!(coli.raw_value(), "a\\,b,c\\nd,e\\\\");
+        assert_eq!(coli.value(), crate::unescape_text(coli.raw_value()).unwrap());
+        assert_eq!(parse_text_list(&coli), categories);
+        let mut writer = crate::writer::Writer::new(Vec::new());
+        writer.write_content_line(&coli).unwrap();
+        assert_eq!(writer.finish(), b"CATEGORIES:a\\,b,c\\nd,e\\\\\r\n");
+
+        let coli = VEventProperty::Resources(Vec::new()).to_content_line().unwrap();
+        assert_eq!((coli.value(), parse_text_list(&coli)), ("", Vec::new()));
+    }
+}
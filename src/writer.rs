@@ -0,0 +1,292 @@
+//! Serialization of content lines and components back to iCalendar text.
+
+use crate::component::Component;
+use crate::vevent::VEventProperty;
+use crate::{ContentLine, IanaProperty, Property, ValueType};
+use std::io::{self, Write};
+
+/// Maximum length of a physical line, in octets, not counting the line break.
+const MAX_LINE: usize = 75;
+
+pub struct Writer<W> {
+    out: W,
+    line_buf: Vec<u8>,
+}
+
+impl<W: Write> Writer<W> {
+    pub fn new(out: W) -> Self {
+        Writer { out, line_buf: Vec::new() }
+    }
+
+    /// Write a content line. A line that retains its original text (see `ParserOptions::retain_raw`) is written
+    /// back unchanged.
+    pub fn write_content_line(&mut self, coli: &ContentLine) -> io::Result<()> {
+        if let Some(raw) = coli.raw() {
+            return self.out.write_all(raw);
+        }
+        let mut line = std::mem::take(&mut self.line_buf);
+        line.clear();
+        line.extend_from_slice(coli.name().as_str().as_bytes());
+        for param in coli.params() {
+            line.push(b';');
+            line.extend_from_slice(param.name().as_str().as_bytes());
+            line.push(b'=');
+            for (i, value) in param.values().enumerate() {
+                if i > 0 {
+                    line.push(b',');
+                }
+                write_param_value(&mut line, value);
+            }
+        }
+        line.push(b':');
+        // A value read from input is written as it was, even if it was not escaped properly; only a TEXT
+        // value that has been set is escaped.
+        match coli.value_type() {
+            ValueType::Text if coli.value_set => escape_text(&mut line, coli.value(), text_separators(coli.name())),
+            _ => line.extend_from_slice(coli.raw_value().as_bytes()),
+        }
+        let result = self.write_folded(&line);
+        self.line_buf = line;
+        result
+    }
+
+    /// Write a component, its properties and its subcomponents. Subcomponents that were read keep their
+    /// place among the properties; those added since follow the properties.
+    pub fn write_component(&mut self, component: &Component) -> io::Result<()> {
+        let name = component.name().as_str();
+        match &component.begin {
+            Some(begin) => self.write_content_line(begin)?,
+            None => self.write_content_line(&ContentLine::new(Property::Begin, Vec::new(), name.to_owned()))?,
+        }
+        let properties = &component.properties;
+        let mut written = 0;
+        for (i, child) in component.children().enumerate() {
+            let at = component.child_positions.get(i).map_or(properties.len(), |&at| at.min(properties.len()));
+            for coli in properties.get(written..at).unwrap_or_default() {
+                self.write_content_line(coli)?;
+            }
+            written = written.max(at);
+            self.write_component(child)?;
+        }
+        for coli in &properties[written..] {
+            self.write_content_line(coli)?;
+        }
+        match &component.end {
+            Some(end) => self.write_content_line(end),
+            None => self.write_content_line(&ContentLine::new(Property::End, Vec::new(), name.to_owned())),
+        }
+    }
+
+    /// Write a typed property. `VEventProperty::Unknown` carries no value, and is skipped.
+    pub fn write_property(&mut self, prop: &VEventProperty) -> io::Result<()> {
+        match prop.to_content_line() {
+            Some(coli) => self.write_content_line(&coli),
+            None => Ok(()),
+        }
+    }
+
+    pub fn flush(&mut self) -> io::Result<()> {
+        self.out.flush()
+    }
+
+    pub fn finish(self) -> W {
+        self.out
+    }
+
+    /// Write a logical line, folded into physical lines of at most 75 octets, each ended with CRLF.
+    /// Lines are only broken between (not within) UTF-8 sequences.
+    fn write_folded(&mut self, mut line: &[u8]) -> io::Result<()> {
+        // Continuation lines begin with a space, which counts toward the limit.
+        let mut limit = MAX_LINE;
+        while line.len() > limit {
+            let mut split = limit;
+            while split > 0 && line[split] & 0xC0 == 0x80 {
+                split -= 1;
+            }
+            self.out.write_all(&line[..split])?;
+            self.out.write_all(b"\r\n ")?;
+            line = &line[split..];
+            limit = MAX_LINE - 1;
+        }
+        self.out.write_all(line)?;
+        self.out.write_all(b"\r\n")
+    }
+}
+
+/// Separators of TEXT properties whose values are lists or structures; these are written unescaped.
+fn text_separators(name: &Property) -> &'static [u8] {
+    match name {
+        Property::Iana(IanaProperty::Categories) | Property::Iana(IanaProperty::Resources) => b",",
+        Property::Iana(IanaProperty::RequestStatus) => b";",
+        _ => b"",
+    }
+}
+
+pub(crate) fn escape_text(out: &mut Vec<u8>, value: &str, separators: &[u8]) {
+    for &c in value.as_bytes() {
+        match c {
+            b'\n' => out.extend_from_slice(b"\\n"),
+            b'\\' | b';' | b',' if !separators.contains(&c) => {
+                out.push(b'\\');
+                out.push(c);
+            }
+            c => out.push(c),
+        }
+    }
+}
+
+/// Write a parameter value, quoted if necessary, with circumflex escapes (RFC 6868) for characters that
+/// cannot otherwise be represented.
+fn write_param_value(out: &mut Vec<u8>, value: &str) {
+    let quote = value.bytes().any(|c| matches!(c, b':' | b';' | b','));
+    if quote {
+        out.push(b'"');
+    }
+    for c in value.bytes() {
+        match c {
+            b'\n' => out.extend_from_slice(b"^n"),
+            b'"' => out.extend_from_slice(b"^'"),
+            b'^' => out.extend_from_slice(b"^^"),
+            c => out.push(c),
+        }
+    }
+    if quote {
+        out.push(b'"');
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::vevent::When;
+    use crate::{IanaParam, Lexer, Param, ParamName, ParserOptions};
+    use chrono::NaiveDate;
+
+    fn write(coli: &ContentLine) -> String {
+        let mut writer = Writer::new(Vec::new());
+        writer.write_content_line(coli).unwrap();
+        String::from_utf8(writer.finish()).unwrap()
+    }
+
+    fn line(name: IanaProperty, params: Vec<Param>, value: &str) -> ContentLine {
+        ContentLine::new(Property::Iana(name), params, value.to_owned())
+    }
+
+    #[test]
+    fn escaping() {
+        assert_eq!(write(&line(IanaProperty::Summary, Vec::new(), "a,b;c\\d\ne")), "SUMMARY:a\\,b\\;c\\\\d\\ne\r\n");
+        assert_eq!(write(&line(IanaProperty::Categories, Vec::new(), "a,b;c")), "CATEGORIES:a,b\\;c\r\n");
+        // Values of other types are not escaped.
+        assert_eq!(write(&line(IanaProperty::Rrule, Vec::new(), "FREQ=DAILY;COUNT=2")), "RRULE:FREQ=DAILY;COUNT=2\r\n");
+    }
+
+    #[test]
+    fn escaping_read_values() {
+        let input = b"SUMMARY:a,b;c\r\nDESCRIPTION:a\\, b\\nc\r\nCOMMENT:C:\\temp\r\nLOCATION:x\r\n";
+        let mut lexer = Lexer::new(&input[..]);
+        let mut lines: Vec<_> = lexer.content_lines().map(Result::unwrap).collect();
+        assert_eq!((lines[0].value(), lines[1].value()), ("a,b;c", "a, b\nc"));
+        // Values read are written as they were, whether or not unescaping changed them.
+        let written: String = lines.iter().map(write).collect();
+        assert_eq!(written.as_bytes(), &input[..]);
+
+        // Values that are set are escaped, even if they are the same as before.
+        lines[0].set_value("a,b;c".to_owned());
+        assert_eq!(write(&lines[0]), "SUMMARY:a\\,b\\;c\r\n");
+        lines[3].set_value("y\n".to_owned());
+        assert_eq!(write(&lines[3]), "LOCATION:y\\n\r\n");
+        // Changing only a parameter leaves the value as it was.
+        lines[1].set_param(ParamName::Iana(IanaParam::Language), vec!["en".to_owned()]);
+        assert_eq!(write(&lines[1]), "DESCRIPTION;LANGUAGE=en:a\\, b\\nc\r\n");
+    }
+
+    #[test]
+    fn parameters() {
+        let params = vec![
+            Param::new(ParamName::Iana(IanaParam::Cn), vec!["Doe, John".to_owned()]),
+            Param::new(ParamName::Iana(IanaParam::Member), vec!["a:b".to_owned(), "c".to_owned()]),
+            Param::new(ParamName::Iana(IanaParam::Language), vec!["say \"hi\"^\n".to_owned()]),
+        ];
+        assert_eq!(write(&line(IanaProperty::Attendee, params, "mailto:j@x")),
+                   "ATTENDEE;CN=\"Doe, John\";MEMBER=\"a:b\",c;LANGUAGE=say ^'hi^'^^^n:mailto:j@x\r\n");
+    }
+
+    #[test]
+    fn folding() {
+        for value in &["x".repeat(200), "é".repeat(100), format!("x{}", "€".repeat(60))] {
+            let written = write(&line(IanaProperty::Description, Vec::new(), value));
+            assert!(written.ends_with("\r\n"));
+            let physical: Vec<_> = written.trim_end_matches("\r\n").split("\r\n").collect();
+            assert!(physical.len() > 1);
+            assert!(physical.iter().all(|l| l.len() <= MAX_LINE));
+            assert!(physical[1..].iter().all(|l| l.starts_with(' ')));
+            // Lines are read back unchanged.
+            let mut lexer = Lexer::new(written.as_bytes());
+            assert_eq!(lexer.lex_content_line().unwrap().unwrap().value(), value);
+        }
+        let short = "x".repeat(MAX_LINE - "SUMMARY:".len());
+        assert_eq!(write(&line(IanaProperty::Summary, Vec::new(), &short)).matches("\r\n").count(), 1);
+    }
+
+    #[test]
+    fn components_and_properties() {
+        let input = "BEGIN:VCALENDAR\r\nVERSION:2.0\r\nBEGIN:VEVENT\r\nUID:abc\r\nEND:VEVENT\r\nEND:VCALENDAR\r\n";
+        let calendar = Component::parse(&mut Lexer::new(input.as_bytes())).unwrap().unwrap();
+        let mut writer = Writer::new(Vec::new());
+        writer.write_component(&calendar).unwrap();
+        writer.write_property(&VEventProperty::Summary("a, b".to_owned())).unwrap();
+        writer.write_property(&VEventProperty::Dtstart(When::Date(NaiveDate::from_ymd_opt(2024, 1, 2).unwrap()))).unwrap();
+        writer.write_property(&VEventProperty::Unknown).unwrap();
+        assert_eq!(String::from_utf8(writer.finish()).unwrap(),
+                   format!("{}SUMMARY:a\\, b\r\nDTSTART;VALUE=DATE:20240102\r\n", input));
+    }
+
+    const CALENDAR: &str = "\r\nBEGIN:VCALENDAR\r\nVERSION:2.0\r\nBEGIN:VEVENT\r\nUID:abc\r\nsummary:Lunch\\, then\r\n  a walk\r\n\r\n\
+                            END:VEVENT\r\nCOLOR:teal\r\nX-WR-CALNAME;X-FOO=\"a:b\":Work\r\nBEGIN:VEVENT\r\nUID:def\r\nEND:VEVENT\r\n\
+                            END:VCALENDAR\r\n\r\n\r\n";
+
+    fn round_trip(calendar: &Component) -> String {
+        let mut writer = Writer::new(Vec::new());
+        writer.write_component(calendar).unwrap();
+        String::from_utf8(writer.finish()).unwrap()
+    }
+
+    #[test]
+    fn lossless() {
+        let options = ParserOptions::strict().retain_raw(true).lowercase_names(true);
+        let mut lexer = Lexer::with_options(CALENDAR.as_bytes(), options);
+        let mut calendar = Component::parse(&mut lexer).unwrap().unwrap();
+        assert!(Component::parse(&mut lexer).unwrap().is_none());
+        assert_eq!(round_trip(&calendar), CALENDAR);
+
+        // Only the modified line is written anew.
+        let event = &mut calendar.children_mut()[0];
+        event.properties_mut()[1].set_value("Dinner".to_owned());
+        assert_eq!(round_trip(&calendar), CALENDAR.replace("summary:Lunch\\, then\r\n  a walk\r\n\r\n", "SUMMARY:Dinner\r\n"));
+
+        // Added subcomponents follow the properties.
+        let event = calendar.children().next().unwrap().clone();
+        calendar.children_mut().push(event);
+        let written = round_trip(&calendar);
+        assert!(written.ends_with("UID:def\r\nEND:VEVENT\r\nBEGIN:VEVENT\r\nUID:abc\r\nSUMMARY:Dinner\r\nEND:VEVENT\r\nEND:VCALENDAR\r\n\r\n\r\n"));
+    }
+
+    #[test]
+    fn lossless_slice() {
+        let mut lexer = crate::slice::Lexer::with_options(CALENDAR.as_bytes(), ParserOptions::lenient().retain_raw(true));
+        let mut written = Vec::new();
+        for coli in lexer.content_lines() {
+            written.extend_from_slice(coli.unwrap().raw().unwrap());
+        }
+        assert_eq!(String::from_utf8(written).unwrap(), CALENDAR);
+    }
+
+    #[test]
+    fn lossless_unregistered_names() {
+        let mut lexer = Lexer::with_options(&b"COLOR:teal\r\n"[..], ParserOptions::strict().retain_raw(true));
+        let coli = lexer.lex_content_line().unwrap().unwrap();
+        assert_eq!(coli.name(), &Property::Other("COLOR".to_owned()));
+        assert_eq!(lexer.warnings()[0].code(), crate::WarningCode::UnregisteredName);
+        assert!(Lexer::new(&b"COLOR:teal\r\n"[..]).lex_content_line().is_err());
+    }
+}